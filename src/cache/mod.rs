@@ -0,0 +1,166 @@
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+type CacheKey = [u8; 32];
+
+struct CacheEntry {
+    user_id: String,
+    expires: Instant,
+}
+
+/// Remembers recently verified `Authorization` headers so repeated HTTP Basic
+/// requests skip Argon2. Headers are never stored: entries are keyed by an
+/// HMAC under a key generated at startup, so a memory dump does not reveal
+/// passwords or allow offline guessing.
+pub struct CredentialCache {
+    key: [u8; 32],
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<HashMap<CacheKey, CacheEntry>>,
+}
+
+impl CredentialCache {
+    pub fn from_config(config: &crate::config::Config) -> Self {
+        CredentialCache {
+            key: rand::thread_rng().gen::<[u8; 32]>(),
+            ttl: Duration::from_secs(config.credential_cache_ttl),
+            capacity: config.credential_cache_size,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        !self.ttl.is_zero() && self.capacity > 0
+    }
+
+    fn key_for(&self, auth_header: &str) -> CacheKey {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.key)
+            .expect("HMAC accepts keys of any size");
+        mac.update(auth_header.as_bytes());
+        mac.finalize().into_bytes().into()
+    }
+
+    pub fn get(&self, auth_header: &str) -> Option<String> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let key = self.key_for(auth_header);
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(&key) {
+            Some(entry) if entry.expires > Instant::now() => Some(entry.user_id.to_string()),
+            Some(_) => {
+                entries.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, auth_header: &str, user_id: &str) {
+        if !self.is_enabled() {
+            return;
+        }
+        let key = self.key_for(auth_header);
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            entries.retain(|_, entry| entry.expires > now);
+        }
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.expires)
+                .map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(
+            key,
+            CacheEntry {
+                user_id: user_id.to_string(),
+                expires: now + self.ttl,
+            },
+        );
+    }
+
+    /// Drops every cached credential of the user, e.g. after a password change.
+    pub fn invalidate_user(&self, user_id: &str) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|_, entry| entry.user_id != user_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(ttl: Duration, capacity: usize) -> CredentialCache {
+        CredentialCache {
+            key: [7; 32],
+            ttl,
+            capacity,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    #[test]
+    fn entries_expire_after_the_ttl() {
+        let cache = cache(Duration::from_millis(50), 10);
+        cache.insert("Basic YWxpY2U6cGFzcw==", "alice");
+        assert_eq!(
+            cache.get("Basic YWxpY2U6cGFzcw==").as_deref(),
+            Some("alice")
+        );
+        assert_eq!(cache.get("Basic Ym9iOnBhc3M="), None);
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(cache.get("Basic YWxpY2U6cGFzcw=="), None);
+        assert!(cache.entries.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn full_cache_evicts_the_oldest_entry() {
+        let cache = cache(Duration::from_secs(60), 2);
+        for (header, user) in [
+            ("Basic a", "alice"),
+            ("Basic b", "bob"),
+            ("Basic c", "carol"),
+        ] {
+            cache.insert(header, user);
+            std::thread::sleep(Duration::from_millis(2));
+        }
+
+        assert_eq!(cache.entries.lock().unwrap().len(), 2);
+        assert_eq!(cache.get("Basic a"), None);
+        assert_eq!(cache.get("Basic b").as_deref(), Some("bob"));
+        assert_eq!(cache.get("Basic c").as_deref(), Some("carol"));
+    }
+
+    #[test]
+    fn invalidate_user_drops_all_their_entries() {
+        let cache = cache(Duration::from_secs(60), 10);
+        cache.insert("Basic old", "alice");
+        cache.insert("Basic new", "alice");
+        cache.insert("Basic b", "bob");
+
+        cache.invalidate_user("alice");
+        assert_eq!(cache.get("Basic old"), None);
+        assert_eq!(cache.get("Basic new"), None);
+        assert_eq!(cache.get("Basic b").as_deref(), Some("bob"));
+    }
+
+    #[test]
+    fn zero_ttl_or_capacity_disables_the_cache() {
+        for cache in [cache(Duration::ZERO, 10), cache(Duration::from_secs(60), 0)] {
+            cache.insert("Basic a", "alice");
+            assert_eq!(cache.get("Basic a"), None);
+        }
+    }
+}
@@ -1,49 +1,282 @@
+use std::collections::HashMap;
 use std::env;
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// Configuration value that must never end up in logs; `Debug` prints a
+/// placeholder and the value is only reachable through `expose`.
+#[derive(Clone)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
 
 #[derive(Debug)]
 pub struct Config {
     pub log_level: String,
+    pub log_format: String,
+    pub log_file: Option<String>,
+    pub log_rotation: String,
+    pub bind_address: IpAddr,
     pub port: u16,
+    pub admin_port: u16,
+    pub concurrency_limit: usize,
+    pub buffer_size: usize,
+    pub rate_limit_requests: u64,
+    pub rate_limit_period: u64,
+    pub request_timeout: u64,
+    pub max_body_size: usize,
+    pub tls_cert_file: Option<String>,
+    pub tls_key_file: Option<String>,
+    pub tls_reload_interval: u64,
+    pub tls_client_ca_file: Option<String>,
+    pub tls_bind_tokens: bool,
+    pub http_redirect_port: Option<u16>,
     pub db_user: String,
-    pub db_password: String,
+    pub db_password: Secret,
     pub db_api_user: String,
-    pub db_api_password: String,
+    pub db_api_password: Secret,
     pub db_host: String,
     pub db_port: u16,
     pub db_name: String,
+    pub db_connect_attempts: u32,
+    pub db_connect_max_backoff: u64,
+    pub db_max_connections: u32,
+    pub db_min_connections: u32,
+    pub db_acquire_timeout: u64,
+    pub reset_token_ttl: i64,
+    pub reset_notifier: Option<String>,
+    pub reset_notifier_file: Option<String>,
+    pub mfa_encryption_key: Secret,
+    pub webauthn_rp_id: String,
+    pub webauthn_origin: String,
+    pub token_ttl: i64,
+    pub purge_interval: u64,
+    pub lockout_account_threshold: u32,
+    pub lockout_ip_threshold: u32,
+    pub lockout_base_delay: u64,
+    pub lockout_max_delay: u64,
+    pub lockout_window: u64,
+    pub argon2_mem_cost: u32,
+    pub argon2_time_cost: u32,
+    pub argon2_parallelism: u32,
+    pub argon2_calibrate_ms: Option<u64>,
+    pub hash_workers: usize,
+    pub hash_queue_depth: usize,
+    pub credential_cache_ttl: u64,
+    pub credential_cache_size: usize,
+    pub password_min_length: usize,
+    pub password_max_length: usize,
+    pub breached_passwords_dir: Option<String>,
+    pub rate_limit_auth_burst: u32,
+    pub rate_limit_auth_rate: f64,
+    pub rate_limit_api_burst: u32,
+    pub rate_limit_api_rate: f64,
+    pub trusted_proxies: Vec<IpAddr>,
+    pub cors_allowed_origins: Vec<String>,
+    pub cors_max_age: u64,
+    pub content_security_policy: String,
+    pub referrer_policy: String,
+    pub permissions_policy: String,
+    pub hsts_max_age: u64,
+    pub hsts_include_subdomains: bool,
+    pub hsts_preload: bool,
 }
 
 impl Config {
-    pub fn new() -> Result<Config, crate::error::Error> {
-        let port = env::var("PORT")
+    /// Reads every setting from the environment, falling back to the
+    /// optional TOML file and then to built-in defaults. File keys are the
+    /// lowercase environment variable names, e.g. `postgres_host = "db"`.
+    pub fn load(config_file: Option<&str>) -> Result<Config, crate::error::Error> {
+        let source = Source::new(config_file)?;
+
+        let port = source
+            .var("PORT")
             .map_err(|_| crate::error::Error::ConfigurationError(String::from("PORT")))?
             .parse::<u16>()
             .map_err(|_| crate::error::Error::ConfigurationError(String::from("PORT")))?;
 
-        let db_user = env::var("POSTGRES_USER")
+        let admin_port = source.parse_or("ADMIN_PORT", 9090)?;
+        let bind_address = source.parse_or("BIND_ADDRESS", IpAddr::from([0, 0, 0, 0]))?;
+        let concurrency_limit = source.parse_or("CONCURRENCY_LIMIT", 5)?;
+        let buffer_size = source.parse_or("BUFFER_SIZE", 5)?;
+        let rate_limit_requests = source.parse_or("RATE_LIMIT_REQUESTS", 100)?;
+        let rate_limit_period = source.parse_or("RATE_LIMIT_PERIOD", 1)?;
+        let request_timeout = source.parse_or("REQUEST_TIMEOUT", 10)?;
+        let max_body_size = source.parse_or("MAX_BODY_SIZE", 64 * 1024)?;
+
+        let tls_cert_file = source.var("TLS_CERT_FILE").ok();
+        let tls_key_file = source.var("TLS_KEY_FILE").ok();
+        let tls_reload_interval = source.parse_or("TLS_RELOAD_INTERVAL", 60)?;
+        let tls_client_ca_file = source.var("TLS_CLIENT_CA_FILE").ok();
+        let tls_bind_tokens = source.parse_or("TLS_BIND_TOKENS", false)?;
+        let http_redirect_port = source
+            .var("HTTP_REDIRECT_PORT")
+            .ok()
+            .map(|value| value.parse::<u16>())
+            .transpose()
+            .map_err(|_| {
+                crate::error::Error::ConfigurationError(String::from("HTTP_REDIRECT_PORT"))
+            })?;
+
+        let db_user = source
+            .var("POSTGRES_USER")
             .map_err(|_| crate::error::Error::ConfigurationError(String::from("POSTGRES_USER")))?;
-        let db_password = env::var("POSTGRES_PASSWORD").map_err(|_| {
+        let db_password = source.var("POSTGRES_PASSWORD").map(Secret).map_err(|_| {
             crate::error::Error::ConfigurationError(String::from("POSTGRES_PASSWORD"))
         })?;
-        let db_api_user = env::var("POSTGRES_API_USER")
+        let db_api_user = source
+            .var("POSTGRES_API_USER")
             .map_err(|_| crate::error::Error::ConfigurationError(String::from("POSTGRES_USER")))?;
-        let db_api_password = env::var("POSTGRES_API_PASSWORD").map_err(|_| {
-            crate::error::Error::ConfigurationError(String::from("POSTGRES_PASSWORD"))
-        })?;
-        let db_host = env::var("POSTGRES_HOST")
+        let db_api_password = source
+            .var("POSTGRES_API_PASSWORD")
+            .map(Secret)
+            .map_err(|_| {
+                crate::error::Error::ConfigurationError(String::from("POSTGRES_PASSWORD"))
+            })?;
+        let db_host = source
+            .var("POSTGRES_HOST")
             .map_err(|_| crate::error::Error::ConfigurationError(String::from("POSTGRES_HOST")))?;
-        let db_port = env::var("POSTGRES_PORT")
+        let db_port = source
+            .var("POSTGRES_PORT")
             .map_err(|_| crate::error::Error::ConfigurationError(String::from("POSTGRES_PORT")))?
             .parse::<u16>()
             .map_err(|_| crate::error::Error::ConfigurationError(String::from("POSTGRES_PORT")))?;
-        let db_name = env::var("POSTGRES_DB")
+        let db_name = source
+            .var("POSTGRES_DB")
             .map_err(|_| crate::error::Error::ConfigurationError(String::from("POSTGRES_DB")))?;
+        let db_connect_attempts = source.parse_or("DB_CONNECT_ATTEMPTS", 10)?;
+        let db_connect_max_backoff = source.parse_or("DB_CONNECT_MAX_BACKOFF", 30)?;
+        let db_max_connections = source.parse_or("DB_MAX_CONNECTIONS", 5)?;
+        let db_min_connections = source.parse_or("DB_MIN_CONNECTIONS", 0)?;
+        let db_acquire_timeout = source.parse_or("DB_ACQUIRE_TIMEOUT", 30)?;
+
+        let webauthn_rp_id = source
+            .var("WEBAUTHN_RP_ID")
+            .unwrap_or(String::from("localhost"));
+        let webauthn_origin = source
+            .var("WEBAUTHN_ORIGIN")
+            .unwrap_or(format!("https://{}", webauthn_rp_id));
+        let token_ttl = source.parse_or("TOKEN_TTL", 3600)?;
+        let purge_interval = source.parse_or("PURGE_INTERVAL", 300)?;
+
+        let log_level = source.var("LOG_LEVEL").unwrap_or(String::from("warn"));
+        let log_format = source.var("LOG_FORMAT").unwrap_or(String::from("json"));
+        let log_file = source.var("LOG_FILE").ok();
+        let log_rotation = source.var("LOG_ROTATION").unwrap_or(String::from("daily"));
+
+        let reset_token_ttl = source.parse_or("RESET_TOKEN_TTL", 900)?;
+        let reset_notifier = source.var("RESET_NOTIFIER").ok();
+        let reset_notifier_file = source.var("RESET_NOTIFIER_FILE").ok();
+        let mfa_encryption_key = source.var("MFA_ENCRYPTION_KEY").map(Secret).map_err(|_| {
+            crate::error::Error::ConfigurationError(String::from("MFA_ENCRYPTION_KEY"))
+        })?;
+
+        let lockout_account_threshold = source.parse_or("LOCKOUT_ACCOUNT_THRESHOLD", 5)?;
+        let lockout_ip_threshold = source.parse_or("LOCKOUT_IP_THRESHOLD", 20)?;
+        let lockout_base_delay = source.parse_or("LOCKOUT_BASE_DELAY", 1)?;
+        let lockout_max_delay = source.parse_or("LOCKOUT_MAX_DELAY", 900)?;
+        let lockout_window = source.parse_or("LOCKOUT_WINDOW", 900)?;
+
+        let argon2_mem_cost = source.parse_or("ARGON2_MEMORY_COST", 19456)?;
+        let argon2_time_cost = source.parse_or("ARGON2_TIME_COST", 2)?;
+        let argon2_parallelism = source.parse_or("ARGON2_PARALLELISM", 1)?;
+        let argon2_calibrate_ms = source
+            .var("ARGON2_CALIBRATE_MS")
+            .ok()
+            .map(|value| value.parse::<u64>())
+            .transpose()
+            .map_err(|_| {
+                crate::error::Error::ConfigurationError(String::from("ARGON2_CALIBRATE_MS"))
+            })?;
 
-        let log_level = env::var("LOG_LEVEL").unwrap_or(String::from("warn"));
+        let hash_workers = source.parse_or(
+            "HASH_WORKERS",
+            std::thread::available_parallelism().map_or(1, |workers| workers.get()),
+        )?;
+        let hash_queue_depth = source.parse_or("HASH_QUEUE_DEPTH", 64)?;
 
-        Ok(Config {
+        let credential_cache_ttl = source.parse_or("CREDENTIAL_CACHE_TTL", 30)?;
+        let credential_cache_size = source.parse_or("CREDENTIAL_CACHE_SIZE", 10_000)?;
+
+        let password_min_length = source.parse_or("PASSWORD_MIN_LENGTH", 8)?;
+        let password_max_length = source.parse_or("PASSWORD_MAX_LENGTH", 64)?;
+        let breached_passwords_dir = source.var("BREACHED_PASSWORDS_DIR").ok();
+
+        let rate_limit_auth_burst = source.parse_or("RATE_LIMIT_AUTH_BURST", 10)?;
+        let rate_limit_auth_rate = source.parse_or("RATE_LIMIT_AUTH_RATE", 0.5)?;
+        let rate_limit_api_burst = source.parse_or("RATE_LIMIT_API_BURST", 60)?;
+        let rate_limit_api_rate = source.parse_or("RATE_LIMIT_API_RATE", 10.0)?;
+        let trusted_proxies = match source.var("TRUSTED_PROXIES") {
+            Ok(value) => value
+                .split(',')
+                .map(str::trim)
+                .filter(|proxy| !proxy.is_empty())
+                .map(|proxy| proxy.parse::<IpAddr>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| {
+                    crate::error::Error::ConfigurationError(String::from("TRUSTED_PROXIES"))
+                })?,
+            Err(_) => Vec::new(),
+        };
+        let cors_allowed_origins: Vec<String> = match source.var("CORS_ALLOWED_ORIGINS") {
+            Ok(value) => value
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .map(|origin| origin.trim_end_matches('/').to_string())
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        let cors_max_age = source.parse_or("CORS_MAX_AGE", 600)?;
+
+        let content_security_policy =
+            source
+                .var("CONTENT_SECURITY_POLICY")
+                .unwrap_or(String::from(
+                    "default-src 'none'; frame-ancestors 'none'; sandbox",
+                ));
+        let referrer_policy = source
+            .var("REFERRER_POLICY")
+            .unwrap_or(String::from("no-referrer"));
+        let permissions_policy = source.var("PERMISSIONS_POLICY").unwrap_or(String::from(
+            "accelerometer=(), camera=(), geolocation=(), microphone=(), payment=(), usb=()",
+        ));
+        let hsts_max_age = source.parse_or("HSTS_MAX_AGE", 31_536_000)?;
+        let hsts_include_subdomains = source.parse_or("HSTS_INCLUDE_SUBDOMAINS", false)?;
+        let hsts_preload = source.parse_or("HSTS_PRELOAD", false)?;
+
+        let config = Config {
             log_level,
+            log_format,
+            log_file,
+            log_rotation,
+            bind_address,
             port,
+            admin_port,
+            concurrency_limit,
+            buffer_size,
+            rate_limit_requests,
+            rate_limit_period,
+            request_timeout,
+            max_body_size,
+            tls_cert_file,
+            tls_key_file,
+            tls_reload_interval,
+            tls_client_ca_file,
+            tls_bind_tokens,
+            http_redirect_port,
             db_user,
             db_password,
             db_api_user,
@@ -51,6 +284,200 @@ impl Config {
             db_host,
             db_port,
             db_name,
-        })
+            db_connect_attempts,
+            db_connect_max_backoff,
+            db_max_connections,
+            db_min_connections,
+            db_acquire_timeout,
+            reset_token_ttl,
+            reset_notifier,
+            reset_notifier_file,
+            mfa_encryption_key,
+            webauthn_rp_id,
+            webauthn_origin,
+            token_ttl,
+            purge_interval,
+            lockout_account_threshold,
+            lockout_ip_threshold,
+            lockout_base_delay,
+            lockout_max_delay,
+            lockout_window,
+            argon2_mem_cost,
+            argon2_time_cost,
+            argon2_parallelism,
+            argon2_calibrate_ms,
+            hash_workers,
+            hash_queue_depth,
+            credential_cache_ttl,
+            credential_cache_size,
+            password_min_length,
+            password_max_length,
+            breached_passwords_dir,
+            rate_limit_auth_burst,
+            rate_limit_auth_rate,
+            rate_limit_api_burst,
+            rate_limit_api_rate,
+            trusted_proxies,
+            cors_allowed_origins,
+            cors_max_age,
+            content_security_policy,
+            referrer_policy,
+            permissions_policy,
+            hsts_max_age,
+            hsts_include_subdomains,
+            hsts_preload,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Rejects settings that would parse but leave the server unusable.
+    fn validate(&self) -> Result<(), crate::error::Error> {
+        let checks = [
+            (self.port != self.admin_port, "ADMIN_PORT"),
+            (self.concurrency_limit > 0, "CONCURRENCY_LIMIT"),
+            (self.buffer_size > 0, "BUFFER_SIZE"),
+            (self.rate_limit_requests > 0, "RATE_LIMIT_REQUESTS"),
+            (self.rate_limit_period > 0, "RATE_LIMIT_PERIOD"),
+            (self.request_timeout > 0, "REQUEST_TIMEOUT"),
+            (self.max_body_size > 0, "MAX_BODY_SIZE"),
+            (
+                self.tls_cert_file.is_some() == self.tls_key_file.is_some(),
+                "TLS_KEY_FILE",
+            ),
+            (self.tls_reload_interval > 0, "TLS_RELOAD_INTERVAL"),
+            // Preload lists reject entries without both of these.
+            (
+                !self.hsts_preload
+                    || (self.hsts_include_subdomains && self.hsts_max_age >= 31_536_000),
+                "HSTS_PRELOAD",
+            ),
+            (
+                self.tls_client_ca_file.is_none() || self.tls_cert_file.is_some(),
+                "TLS_CLIENT_CA_FILE",
+            ),
+            (
+                !self.tls_bind_tokens || self.tls_client_ca_file.is_some(),
+                "TLS_BIND_TOKENS",
+            ),
+            (
+                self.http_redirect_port.is_none_or(|port| {
+                    self.tls_cert_file.is_some() && port != self.port && port != self.admin_port
+                }),
+                "HTTP_REDIRECT_PORT",
+            ),
+            (self.db_connect_attempts > 0, "DB_CONNECT_ATTEMPTS"),
+            (self.db_max_connections > 0, "DB_MAX_CONNECTIONS"),
+            (
+                self.db_min_connections <= self.db_max_connections,
+                "DB_MIN_CONNECTIONS",
+            ),
+            (self.db_acquire_timeout > 0, "DB_ACQUIRE_TIMEOUT"),
+            (self.token_ttl > 0, "TOKEN_TTL"),
+            (self.purge_interval > 0, "PURGE_INTERVAL"),
+            (self.reset_token_ttl > 0, "RESET_TOKEN_TTL"),
+            // Both notifiers are for development only and must be chosen
+            // explicitly, so tokens never end up in production logs. Without
+            // one, password resets are disabled.
+            (
+                match self.reset_notifier.as_deref() {
+                    None | Some("console") => true,
+                    Some("file") => self.reset_notifier_file.is_some(),
+                    _ => false,
+                },
+                "RESET_NOTIFIER",
+            ),
+            (
+                self.lockout_base_delay <= self.lockout_max_delay,
+                "LOCKOUT_BASE_DELAY",
+            ),
+            (self.hash_workers > 0, "HASH_WORKERS"),
+            (
+                self.password_min_length <= self.password_max_length,
+                "PASSWORD_MIN_LENGTH",
+            ),
+            (self.rate_limit_auth_burst > 0, "RATE_LIMIT_AUTH_BURST"),
+            (self.rate_limit_api_burst > 0, "RATE_LIMIT_API_BURST"),
+            // Refill rates must be positive, otherwise an empty bucket
+            // never recovers.
+            (
+                is_positive(self.rate_limit_auth_rate),
+                "RATE_LIMIT_AUTH_RATE",
+            ),
+            (is_positive(self.rate_limit_api_rate), "RATE_LIMIT_API_RATE"),
+            // A wildcard cannot be combined with credentials, and origins
+            // never carry a path.
+            (
+                self.cors_allowed_origins.iter().all(|origin| {
+                    origin.split_once("://").is_some_and(|(scheme, host)| {
+                        matches!(scheme, "http" | "https")
+                            && !host.is_empty()
+                            && !host.contains('/')
+                    })
+                }),
+                "CORS_ALLOWED_ORIGINS",
+            ),
+        ];
+        match checks.iter().find(|(valid, _)| !valid) {
+            Some((_, name)) => Err(crate::error::Error::ConfigurationError(String::from(*name))),
+            None => Ok(()),
+        }
+    }
+}
+
+fn is_positive(rate: f64) -> bool {
+    rate.is_finite() && rate > 0.0
+}
+
+/// Setting lookup with environment variables taking precedence over the
+/// config file.
+struct Source {
+    file: HashMap<String, String>,
+}
+
+impl Source {
+    fn new(config_file: Option<&str>) -> Result<Self, crate::error::Error> {
+        let mut file = HashMap::new();
+        if let Some(path) = config_file {
+            let error = |reason: String| {
+                crate::error::Error::ConfigurationError(format!("{}: {}", path, reason))
+            };
+            let table = fs::read_to_string(path)
+                .map_err(|e| error(e.to_string()))?
+                .parse::<toml::Table>()
+                .map_err(|e| error(e.to_string()))?;
+            for (key, value) in table {
+                let value = match value {
+                    toml::Value::String(value) => value,
+                    toml::Value::Integer(value) => value.to_string(),
+                    toml::Value::Float(value) => value.to_string(),
+                    toml::Value::Boolean(value) => value.to_string(),
+                    toml::Value::Array(values) => values
+                        .iter()
+                        .map(|value| match value {
+                            toml::Value::String(value) => value.clone(),
+                            value => value.to_string(),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    _ => return Err(error(format!("unsupported value for {}", key))),
+                };
+                file.insert(key.to_uppercase(), value);
+            }
+        }
+        Ok(Source { file })
+    }
+
+    fn var(&self, name: &str) -> Result<String, env::VarError> {
+        env::var(name).or_else(|e| self.file.get(name).cloned().ok_or(e))
+    }
+
+    fn parse_or<T: FromStr>(&self, name: &str, default: T) -> Result<T, crate::error::Error> {
+        match self.var(name) {
+            Ok(value) => value
+                .parse::<T>()
+                .map_err(|_| crate::error::Error::ConfigurationError(String::from(name))),
+            Err(_) => Ok(default),
+        }
     }
 }
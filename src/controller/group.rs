@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse, Extension, Json};
+use hyper::StatusCode;
+
+use crate::extract::{JsonBody, PathParams};
+use crate::model::{
+    group::{GroupId, NewGroup, NewGroupCreated, NewGroupMember, NewGroupMemberCreated},
+    user::Session,
+};
+use crate::validation::Username;
+
+pub async fn create_group(
+    State(store): State<Arc<crate::store::Store>>,
+    Extension(current_session): Extension<Session>,
+    JsonBody(new_group): JsonBody<NewGroup>,
+) -> impl IntoResponse {
+    if let Some(value) = current_session
+        .get_error_if_user_not_match(&new_group.owner, "Owner must match authenticated user")
+    {
+        return Err(value);
+    }
+
+    match create(store, new_group).await {
+        Ok(group) => Ok(Json(group)),
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn add_member(
+    State(store): State<Arc<crate::store::Store>>,
+    Extension(current_session): Extension<Session>,
+    PathParams(group_id): PathParams<GroupId>,
+    JsonBody(new_member): JsonBody<NewGroupMember>,
+) -> impl IntoResponse {
+    check_group_owner(&store, &current_session, &group_id).await?;
+
+    match store
+        .add_group_member(&group_id, &new_member.username)
+        .await
+    {
+        Ok(_) => Ok(Json(NewGroupMemberCreated {
+            uri: format!("/groups/{}/members/{}", &group_id.0, &new_member.username),
+            username: new_member.username.into(),
+        })),
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn remove_member(
+    State(store): State<Arc<crate::store::Store>>,
+    Extension(current_session): Extension<Session>,
+    PathParams((group_id, username)): PathParams<(GroupId, Username)>,
+) -> impl IntoResponse {
+    check_group_owner(&store, &current_session, &group_id).await?;
+
+    match store.remove_group_member(&group_id, &username).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(crate::error::Error::IllegalArgumentException(String::from(
+            "User is not a member of the group",
+        ))),
+        Err(e) => Err(e),
+    }
+}
+
+async fn check_group_owner(
+    store: &crate::store::Store,
+    current_session: &Session,
+    group_id: &GroupId,
+) -> Result<(), crate::error::Error> {
+    let message = "Only the group owner can manage members";
+    current_session.get_username_or_error(message)?;
+
+    match store.get_group_by_id(group_id).await? {
+        Some(group) => match current_session.get_error_if_user_not_match(&group.owner, message) {
+            Some(e) => Err(e),
+            None => Ok(()),
+        },
+        None => Err(crate::error::Error::IllegalArgumentException(String::from(
+            "Unknown group",
+        ))),
+    }
+}
+
+async fn create(
+    store: Arc<crate::store::Store>,
+    new_group: NewGroup,
+) -> Result<NewGroupCreated, crate::error::Error> {
+    match store.create_group(new_group).await {
+        Ok(group) => Ok(NewGroupCreated {
+            name: group.name.to_string(),
+            uri: format!("/groups/{}", &group.group_id.unwrap().0),
+        }),
+        Err(e) => Err(e),
+    }
+}
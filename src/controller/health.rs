@@ -0,0 +1,40 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse, Extension, Json};
+use hyper::StatusCode;
+use serde_json::json;
+
+use crate::mfa::SecretBox;
+
+/// Liveness: answering at all means the process is up.
+pub async fn healthz() -> impl IntoResponse {
+    Json(json!({ "status": "ok" }))
+}
+
+/// Readiness: only route traffic here once every dependency is usable.
+/// Migrations need no check of their own since startup fails without them.
+pub async fn readyz(
+    State(store): State<Arc<crate::store::Store>>,
+    Extension(secret_box): Extension<Arc<SecretBox>>,
+) -> impl IntoResponse {
+    let database = store.ping().await.is_ok();
+    let key_material = secret_box.self_test();
+
+    let ready = database && key_material;
+    let check = |ok: bool| if ok { "ok" } else { "failed" };
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(json!({
+            "status": if ready { "ready" } else { "not_ready" },
+            "checks": {
+                "database": check(database),
+                "key_material": check(key_material),
+            },
+        })),
+    )
+}
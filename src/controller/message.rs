@@ -1,8 +1,8 @@
 use std::sync::Arc;
 
 use axum::{extract::State, response::IntoResponse, Extension, Json};
-use regex::Regex;
 
+use crate::extract::JsonBody;
 use crate::model::{
     message::{Message, NewMessage, NewMessageCreated},
     user::Session,
@@ -11,31 +11,29 @@ use crate::model::{
 pub async fn create_message(
     State(store): State<Arc<crate::store::Store>>,
     Extension(current_session): Extension<Session>,
-    Json(new_message): Json<NewMessage>,
+    JsonBody(new_message): JsonBody<NewMessage>,
 ) -> impl IntoResponse {
-    if new_message.msg_text.chars().count() > 1024 {
-        return Err(crate::error::Error::IllegalArgumentException(String::from(
-            "Message text too long",
-        )));
-    }
-    let re = Regex::new(r"^[a-zA-Z][a-zA-Z0-9]{1,29}$").unwrap();
-    if !re.is_match(&new_message.author) {
-        return Err(crate::error::Error::IllegalArgumentException(String::from(
-            "Invalid author",
-        )));
-    }
     if let Some(value) = current_session
         .get_error_if_user_not_match(&new_message.author, "Author must match authenticated user")
     {
         return Err(value);
     }
+    if !store
+        .get_permissions(&new_message.space_id, &new_message.author)
+        .await?
+        .allows("w")
+    {
+        return Err(crate::error::Error::AuthorizationError(String::from(
+            "Insufficient permissions to post to the space",
+        )));
+    }
     match create(
         store,
         Message {
             msg_id: None,
             space_id: new_message.space_id,
-            author: new_message.author,
-            msg_text: new_message.msg_text,
+            author: new_message.author.into(),
+            msg_text: new_message.msg_text.into(),
             msg_time: chrono::Utc::now(),
         },
     )
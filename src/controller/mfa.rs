@@ -0,0 +1,83 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse, Extension, Json};
+
+use crate::{
+    cache::CredentialCache,
+    error::Error,
+    extract::{JsonBody, PathParams},
+    mfa::SecretBox,
+    model::user::{MfaConfirmation, MfaConfirmed, MfaEnrollment, Session},
+    validation::Username,
+};
+
+pub async fn enroll(
+    State(store): State<Arc<crate::store::Store>>,
+    Extension(secret_box): Extension<Arc<SecretBox>>,
+    Extension(current_session): Extension<Session>,
+    PathParams(user_id): PathParams<Username>,
+) -> impl IntoResponse {
+    if let Some(value) =
+        current_session.get_error_if_user_not_match(&user_id, "Only the user can enroll in MFA")
+    {
+        return Err(value);
+    }
+
+    let secret = crate::mfa::generate_secret();
+    let sealed = secret_box.seal(&secret, &user_id)?;
+    if !store.save_mfa_enrollment(&user_id, &sealed).await? {
+        return Err(Error::IllegalArgumentException(String::from(
+            "MFA is already enabled",
+        )));
+    }
+
+    Ok(Json(MfaEnrollment {
+        otpauth_uri: crate::mfa::otpauth_uri(&secret, &user_id),
+    }))
+}
+
+pub async fn confirm(
+    State(store): State<Arc<crate::store::Store>>,
+    Extension(secret_box): Extension<Arc<SecretBox>>,
+    Extension(cache): Extension<Arc<CredentialCache>>,
+    Extension(current_session): Extension<Session>,
+    PathParams(user_id): PathParams<Username>,
+    JsonBody(confirmation): JsonBody<MfaConfirmation>,
+) -> impl IntoResponse {
+    if let Some(value) = current_session
+        .get_error_if_user_not_match(&user_id, "Only the user can confirm MFA enrollment")
+    {
+        return Err(value);
+    }
+
+    let credential = match store.get_mfa_credential(&user_id).await? {
+        Some(credential) if !credential.confirmed => credential,
+        Some(_) => {
+            return Err(Error::IllegalArgumentException(String::from(
+                "MFA is already enabled",
+            )))
+        }
+        None => {
+            return Err(Error::IllegalArgumentException(String::from(
+                "No pending MFA enrollment",
+            )))
+        }
+    };
+
+    let secret = secret_box.open(&credential.secret, &user_id)?;
+    let step = crate::mfa::verify_code(&secret, &confirmation.code, chrono::Utc::now())
+        .ok_or_else(|| Error::IllegalArgumentException(String::from("Invalid code")))?;
+
+    let recovery_codes = crate::mfa::generate_recovery_codes();
+    let recovery_code_hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| super::user::hash_token(code))
+        .collect();
+    store
+        .confirm_mfa(&user_id, step, &recovery_code_hashes)
+        .await?;
+    // Cached password-only logins must not outlive the switch to MFA.
+    cache.invalidate_user(&user_id);
+
+    Ok(Json(MfaConfirmed { recovery_codes }))
+}
@@ -1,3 +1,8 @@
+pub mod group;
+pub mod health;
 pub mod message;
+pub mod mfa;
+pub mod problem;
 pub mod space;
 pub mod user;
+pub mod webauthn;
@@ -0,0 +1,17 @@
+use axum::{response::IntoResponse, Json};
+
+use crate::{
+    error::{problem_type, Error, PROBLEM_TYPES},
+    extract::PathParams,
+};
+
+pub async fn list_problem_types() -> impl IntoResponse {
+    Json(PROBLEM_TYPES.as_slice())
+}
+
+pub async fn get_problem_type(PathParams(code): PathParams<String>) -> impl IntoResponse {
+    match problem_type(&code) {
+        Some(problem) => Ok(Json(problem)),
+        None => Err(Error::NotFound(format!("Unknown problem type {}", code))),
+    }
+}
@@ -1,27 +1,18 @@
 use std::sync::Arc;
 
 use axum::{extract::State, response::IntoResponse, Extension, Json};
-use regex::Regex;
 
-use crate::model::user::Session;
+use crate::extract::{JsonBody, PathParams};
+use crate::model::{
+    space::{NewSpace, NewSpaceMember, NewSpaceMemberCreated, SpaceId},
+    user::Session,
+};
 
 pub async fn create_space(
     State(store): State<Arc<crate::store::Store>>,
     Extension(current_session): Extension<Session>,
-    Json(new_space): Json<crate::model::space::Space>,
+    JsonBody(new_space): JsonBody<NewSpace>,
 ) -> impl IntoResponse {
-    if new_space.name.chars().count() > 255 {
-        return Err(crate::error::Error::IllegalArgumentException(String::from(
-            "Space name too long",
-        )));
-    }
-    let re = Regex::new(r"^[a-zA-Z][a-zA-Z0-9]{1,29}$").unwrap();
-    if !re.is_match(&new_space.owner) {
-        return Err(crate::error::Error::IllegalArgumentException(String::from(
-            "Invalid username",
-        )));
-    }
-
     if let Some(value) = current_session
         .get_error_if_user_not_match(&new_space.owner, "Owner must match authenticated user")
     {
@@ -34,13 +25,60 @@ pub async fn create_space(
     }
 }
 
+pub async fn add_member(
+    State(store): State<Arc<crate::store::Store>>,
+    Extension(current_session): Extension<Session>,
+    PathParams(space_id): PathParams<SpaceId>,
+    JsonBody(new_member): JsonBody<NewSpaceMember>,
+) -> impl IntoResponse {
+    let username = current_session.get_username_or_error("Only space members can add members")?;
+    if !store
+        .get_permissions(&space_id, username)
+        .await?
+        .allows("rwd")
+    {
+        return Err(crate::error::Error::AuthorizationError(String::from(
+            "Insufficient permissions to add members",
+        )));
+    }
+
+    match (&new_member.username, &new_member.group_id) {
+        (Some(member), None) => {
+            store
+                .add_user_permissions(&space_id, member, &new_member.permissions)
+                .await?;
+            Ok(Json(NewSpaceMemberCreated {
+                uri: format!("/spaces/{}/members/{}", &space_id.0, member),
+                permissions: new_member.permissions,
+            }))
+        }
+        (None, Some(group_id)) => {
+            if store.get_group_by_id(group_id).await?.is_none() {
+                return Err(crate::error::Error::IllegalArgumentException(String::from(
+                    "Unknown group",
+                )));
+            }
+            store
+                .add_group_permissions(&space_id, group_id, &new_member.permissions)
+                .await?;
+            Ok(Json(NewSpaceMemberCreated {
+                uri: format!("/spaces/{}/groups/{}", &space_id.0, &group_id.0),
+                permissions: new_member.permissions,
+            }))
+        }
+        _ => Err(crate::error::Error::IllegalArgumentException(String::from(
+            "Either username or group_id must be provided",
+        ))),
+    }
+}
+
 async fn create(
     store: Arc<crate::store::Store>,
-    new_space: crate::model::space::Space,
+    new_space: NewSpace,
 ) -> Result<crate::model::space::NewSpaceCreated, crate::error::Error> {
     match store.create_space(new_space).await {
         Ok(space) => Ok(crate::model::space::NewSpaceCreated {
-            name: format!("{}", &space.name),
+            name: space.name.to_string(),
             uri: format!("/spaces/{}", &space.space_id.unwrap().0),
         }),
         Err(e) => Err(e),
@@ -0,0 +1,249 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse, Extension, Json};
+use base64::{engine::general_purpose, Engine as _};
+use rand::Rng;
+
+use crate::{
+    error::Error,
+    extract::{JsonBody, PathParams},
+    model::{
+        user::{Session, Token, TokenConfirmation},
+        webauthn::{
+            AuthenticatorSelection, CredentialCreationOptions, CredentialDescriptor,
+            CredentialParameters, CredentialRequestOptions, NewWebauthnCredential,
+            NewWebauthnCredentialCreated, RelyingPartyEntity, UserEntity, WebauthnAssertion,
+            WebauthnCredential, WebauthnLoginRequest,
+        },
+    },
+    tls::ClientCertificate,
+    validation::Username,
+    webauthn::RelyingParty,
+};
+
+const CEREMONY_REGISTER: &str = "register";
+const CEREMONY_LOGIN: &str = "login";
+const CHALLENGE_TTL_SECS: i64 = 300;
+
+pub async fn start_registration(
+    State(store): State<Arc<crate::store::Store>>,
+    Extension(relying_party): Extension<Arc<RelyingParty>>,
+    Extension(current_session): Extension<Session>,
+    PathParams(user_id): PathParams<Username>,
+) -> impl IntoResponse {
+    if let Some(value) = current_session
+        .get_error_if_user_not_match(&user_id, "Only the user can register credentials")
+    {
+        return Err(value);
+    }
+
+    let challenge = create_challenge(&store, Some(&user_id), CEREMONY_REGISTER).await?;
+    let exclude_credentials = store
+        .get_webauthn_credential_ids(&user_id)
+        .await?
+        .into_iter()
+        .map(to_descriptor)
+        .collect();
+
+    Ok(Json(CredentialCreationOptions {
+        challenge,
+        rp: RelyingPartyEntity {
+            id: relying_party.id.to_string(),
+            name: relying_party.name.to_string(),
+        },
+        user: UserEntity {
+            id: general_purpose::URL_SAFE_NO_PAD.encode(user_id.as_bytes()),
+            name: user_id.to_string(),
+            display_name: user_id.into(),
+        },
+        pub_key_cred_params: vec![CredentialParameters {
+            credential_type: String::from("public-key"),
+            alg: crate::webauthn::COSE_ALG_ES256,
+        }],
+        exclude_credentials,
+        authenticator_selection: AuthenticatorSelection {
+            resident_key: String::from("required"),
+            require_resident_key: true,
+            user_verification: String::from("required"),
+        },
+        timeout: (CHALLENGE_TTL_SECS * 1000) as u64,
+        attestation: String::from("none"),
+    }))
+}
+
+pub async fn finish_registration(
+    State(store): State<Arc<crate::store::Store>>,
+    Extension(relying_party): Extension<Arc<RelyingParty>>,
+    Extension(current_session): Extension<Session>,
+    PathParams(user_id): PathParams<Username>,
+    JsonBody(new_credential): JsonBody<NewWebauthnCredential>,
+) -> impl IntoResponse {
+    if let Some(value) = current_session
+        .get_error_if_user_not_match(&user_id, "Only the user can register credentials")
+    {
+        return Err(value);
+    }
+
+    let client_data_json = crate::webauthn::decode(&new_credential.client_data_json)?;
+    let client_data = relying_party.verify_client_data(&client_data_json, "webauthn.create")?;
+    match store
+        .consume_webauthn_challenge(&client_data.challenge, CEREMONY_REGISTER)
+        .await?
+    {
+        Some(Some(challenge_user)) if *challenge_user == *user_id => {}
+        _ => {
+            return Err(Error::AuthenticationError(String::from(
+                "Invalid or expired challenge",
+            )))
+        }
+    }
+
+    let registered = relying_party.verify_registration(&crate::webauthn::decode(
+        &new_credential.attestation_object,
+    )?)?;
+    if registered.credential_id != new_credential.id.trim_end_matches('=') {
+        return Err(Error::IllegalArgumentException(String::from(
+            "Credential id does not match attestation",
+        )));
+    }
+
+    let credential = store
+        .create_webauthn_credential(WebauthnCredential {
+            credential_id: registered.credential_id,
+            user_id: user_id.into(),
+            public_key: registered.public_key,
+            sign_count: registered.sign_count as i64,
+        })
+        .await?;
+
+    Ok(Json(NewWebauthnCredentialCreated {
+        uri: format!(
+            "/users/{}/webauthn/{}",
+            &credential.user_id, &credential.credential_id
+        ),
+        credential_id: credential.credential_id,
+    }))
+}
+
+/// Starts a login with a discoverable credential. The options never list the
+/// user's credentials or bind the challenge to a user, as either would reveal
+/// which usernames are registered.
+pub async fn start_login(
+    State(store): State<Arc<crate::store::Store>>,
+    Extension(relying_party): Extension<Arc<RelyingParty>>,
+    JsonBody(_login_request): JsonBody<WebauthnLoginRequest>,
+) -> Result<Json<CredentialRequestOptions>, Error> {
+    let challenge = create_challenge(&store, None, CEREMONY_LOGIN).await?;
+
+    Ok(Json(CredentialRequestOptions {
+        challenge,
+        rp_id: relying_party.id.to_string(),
+        allow_credentials: vec![],
+        timeout: (CHALLENGE_TTL_SECS * 1000) as u64,
+        user_verification: String::from("required"),
+    }))
+}
+
+/// Verifies a WebAuthn assertion and issues a short-lived bearer token that
+/// the `authenticate` middleware accepts in place of a password.
+pub async fn finish_login(
+    State(store): State<Arc<crate::store::Store>>,
+    Extension(relying_party): Extension<Arc<RelyingParty>>,
+    Extension(config): Extension<Arc<crate::config::Config>>,
+    client_certificate: Option<Extension<ClientCertificate>>,
+    JsonBody(assertion): JsonBody<WebauthnAssertion>,
+) -> impl IntoResponse {
+    // Bind the token to the client certificate so it is useless without
+    // the matching private key (RFC 8705).
+    let x5t_s256 = client_certificate
+        .filter(|_| config.tls_bind_tokens)
+        .map(|Extension(certificate)| certificate.thumbprint());
+    let result = login_with_assertion(&store, &relying_party, &config, assertion, x5t_s256).await;
+    crate::metrics::metrics().record_auth("webauthn", result.is_ok());
+    result
+}
+
+async fn login_with_assertion(
+    store: &crate::store::Store,
+    relying_party: &RelyingParty,
+    config: &crate::config::Config,
+    assertion: WebauthnAssertion,
+    x5t_s256: Option<String>,
+) -> Result<Json<Token>, Error> {
+    let client_data_json = crate::webauthn::decode(&assertion.client_data_json)?;
+    let client_data = relying_party.verify_client_data(&client_data_json, "webauthn.get")?;
+    if store
+        .consume_webauthn_challenge(&client_data.challenge, CEREMONY_LOGIN)
+        .await?
+        .is_none()
+    {
+        return Err(Error::AuthenticationError(String::from(
+            "Invalid or expired challenge",
+        )));
+    }
+
+    let credential = store
+        .get_webauthn_credential(assertion.id.trim_end_matches('='))
+        .await?
+        .ok_or_else(|| Error::AuthenticationError(String::from("Unknown credential")))?;
+
+    let sign_count = relying_party.verify_assertion(
+        &credential.public_key,
+        credential.sign_count as u32,
+        &crate::webauthn::decode(&assertion.authenticator_data)?,
+        &client_data_json,
+        &crate::webauthn::decode(&assertion.signature)?,
+    )?;
+    // Checked again atomically, as concurrent logins may race past the
+    // counter check above.
+    if !store
+        .update_webauthn_sign_count(&credential.credential_id, sign_count as i64)
+        .await?
+    {
+        tracing::event!(
+            tracing::Level::WARN,
+            "controller::webauthn possibly cloned authenticator for {}",
+            &credential.user_id
+        );
+        return Err(Error::AuthenticationError(String::from(
+            "Signature counter did not increase",
+        )));
+    }
+
+    let token = general_purpose::URL_SAFE_NO_PAD.encode(rand::thread_rng().gen::<[u8; 32]>());
+    let expiry = chrono::Utc::now() + chrono::Duration::seconds(config.token_ttl);
+    store
+        .create_token(
+            &super::user::hash_token(&token),
+            &credential.user_id,
+            expiry,
+            x5t_s256.as_deref(),
+        )
+        .await?;
+
+    Ok(Json(Token {
+        token,
+        expiry,
+        cnf: x5t_s256.map(|x5t_s256| TokenConfirmation { x5t_s256 }),
+    }))
+}
+
+async fn create_challenge(
+    store: &crate::store::Store,
+    user_id: Option<&str>,
+    ceremony: &str,
+) -> Result<String, crate::error::Error> {
+    let challenge = crate::webauthn::generate_challenge();
+    let expiry = chrono::Utc::now() + chrono::Duration::seconds(CHALLENGE_TTL_SECS);
+    store
+        .create_webauthn_challenge(&challenge, user_id, ceremony, expiry)
+        .await?;
+    Ok(challenge)
+}
+
+fn to_descriptor(credential_id: String) -> CredentialDescriptor {
+    CredentialDescriptor {
+        credential_type: String::from("public-key"),
+        id: credential_id,
+    }
+}
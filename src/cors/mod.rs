@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use hyper::header::{self, HeaderName, HeaderValue};
+use hyper::Method;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+use crate::controller::user::OTP_HEADER;
+use crate::request_id::REQUEST_ID_HEADER;
+
+const CSRF_HEADER: HeaderName = HeaderName::from_static("x-csrf-token");
+
+/// Builds the CORS policy for the configured origins, or `None` when no
+/// origin is allowed and browsers should keep enforcing same-origin.
+/// Credentials are allowed, which is safe only because the allow-list is
+/// exact: unlisted origins receive no `Access-Control-Allow-Origin` at all.
+pub fn layer_from_config(
+    config: &crate::config::Config,
+) -> Result<Option<CorsLayer>, crate::error::Error> {
+    if config.cors_allowed_origins.is_empty() {
+        return Ok(None);
+    }
+    let origins = config
+        .cors_allowed_origins
+        .iter()
+        .map(|origin| HeaderValue::from_str(origin))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| {
+            crate::error::Error::ConfigurationError(String::from("CORS_ALLOWED_ORIGINS"))
+        })?;
+
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(AllowOrigin::list(origins))
+            .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+            .allow_headers([
+                header::AUTHORIZATION,
+                header::CONTENT_TYPE,
+                CSRF_HEADER,
+                OTP_HEADER,
+                REQUEST_ID_HEADER,
+            ])
+            .expose_headers([
+                REQUEST_ID_HEADER,
+                header::RETRY_AFTER,
+                header::WWW_AUTHENTICATE,
+                HeaderName::from_static("ratelimit-limit"),
+                HeaderName::from_static("ratelimit-remaining"),
+                HeaderName::from_static("ratelimit-reset"),
+            ])
+            .allow_credentials(true)
+            .max_age(Duration::from_secs(config.cors_max_age)),
+    ))
+}
@@ -1,41 +1,119 @@
 use axum::{
+    body::{boxed, Full},
+    extract::rejection::{JsonRejection, PathRejection},
+    middleware::Next,
     response::{IntoResponse, Response},
     Json,
 };
-use hyper::StatusCode;
+use hyper::{header, Method, Request, StatusCode, Uri};
 use serde_json::json;
 
+use crate::request_id::RequestId;
+
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     ConfigurationError(String),
     DatabaseQueryError(sqlx::Error),
+    MigrationError(sqlx::migrate::MigrateError),
     IllegalArgumentException(String),
     AuthenticationError(String),
     AuthorizationError(String),
     ServerError(hyper::Error),
+    NotificationError(String),
+    CryptoError(String),
+    TooManyRequests(std::time::Duration),
+    Conflict(String),
+    ServiceUnavailable(String),
+    PolicyViolation(Vec<String>),
+    UnsupportedMediaType(String),
+    PayloadTooLarge(String),
+    NotFound(String),
+    MethodNotAllowed(Method),
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match &*self {
+        match self {
             Error::ConfigurationError(ref err) => {
                 write!(f, "Invalid or missed configuration parameter: {}", err)
             }
             Error::DatabaseQueryError(ref err) => {
                 write!(f, "Query could not be executed: {}", err)
             }
+            Error::MigrationError(ref err) => {
+                write!(f, "Migrations could not be applied: {}", err)
+            }
             Error::IllegalArgumentException(ref err) => {
                 write!(f, "Invalid input: {}", err)
             }
             Error::AuthenticationError(ref err) => {
-                write!(f, "Forbidden: {}", err)
+                write!(f, "Unauthorized: {}", err)
             }
             Error::AuthorizationError(ref err) => {
-                write!(f, "Unauthorized: {}", err)
+                write!(f, "Forbidden: {}", err)
             }
             Error::ServerError(ref err) => {
                 write!(f, "Server error: {}", err)
             }
+            Error::NotificationError(ref err) => {
+                write!(f, "Notification could not be delivered: {}", err)
+            }
+            Error::CryptoError(ref err) => {
+                write!(f, "Cryptographic operation failed: {}", err)
+            }
+            Error::TooManyRequests(ref retry_after) => {
+                write!(f, "Too many requests, retry after {:?}", retry_after)
+            }
+            Error::Conflict(ref err) => {
+                write!(f, "Conflict: {}", err)
+            }
+            Error::ServiceUnavailable(ref err) => {
+                write!(f, "Service unavailable: {}", err)
+            }
+            Error::PolicyViolation(ref violations) => {
+                write!(f, "Policy violation: {}", violations.join(", "))
+            }
+            Error::UnsupportedMediaType(ref err) => {
+                write!(f, "Unsupported media type: {}", err)
+            }
+            Error::PayloadTooLarge(ref err) => {
+                write!(f, "Payload too large: {}", err)
+            }
+            Error::NotFound(ref err) => {
+                write!(f, "Not found: {}", err)
+            }
+            Error::MethodNotAllowed(ref method) => {
+                write!(f, "Method not allowed: {}", method)
+            }
+        }
+    }
+}
+
+/// Challenges sent with every 401 so clients know which schemes to retry with.
+const AUTHENTICATE_CHALLENGES: [&str; 2] = [r#"Basic realm="/", charset="UTF-8""#, "Bearer"];
+
+impl Error {
+    /// Stable, machine-readable identifier of the error kind for API clients.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::ConfigurationError(_) => "internal_error",
+            Error::DatabaseQueryError(_) => "internal_error",
+            Error::MigrationError(_) => "internal_error",
+            Error::IllegalArgumentException(_) => "invalid_input",
+            Error::AuthenticationError(_) => "authentication_required",
+            Error::AuthorizationError(_) => "permission_denied",
+            Error::ServerError(_) => "internal_error",
+            Error::NotificationError(_) => "internal_error",
+            Error::CryptoError(_) => "internal_error",
+            Error::TooManyRequests(_) => "rate_limited",
+            Error::Conflict(_) => "conflict",
+            Error::ServiceUnavailable(_) => "service_unavailable",
+            Error::PolicyViolation(_) => "password_policy_violation",
+            Error::UnsupportedMediaType(_) => "unsupported_media_type",
+            Error::PayloadTooLarge(_) => "payload_too_large",
+            Error::NotFound(_) => "not_found",
+            Error::MethodNotAllowed(_) => "method_not_allowed",
         }
     }
 }
@@ -46,8 +124,156 @@ impl From<hyper::Error> for Error {
     }
 }
 
+impl From<JsonRejection> for Error {
+    fn from(value: JsonRejection) -> Self {
+        match value.status() {
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => Error::UnsupportedMediaType(String::from(
+                "Expected request with `Content-Type: application/json`",
+            )),
+            StatusCode::PAYLOAD_TOO_LARGE => Error::PayloadTooLarge(value.body_text()),
+            _ => Error::IllegalArgumentException(value.body_text()),
+        }
+    }
+}
+
+impl From<PathRejection> for Error {
+    fn from(value: PathRejection) -> Self {
+        match value {
+            PathRejection::FailedToDeserializePathParams(_) => {
+                Error::IllegalArgumentException(value.body_text())
+            }
+            // Only raised when a handler is mounted on a route without
+            // parameters.
+            _ => Error::ConfigurationError(value.body_text()),
+        }
+    }
+}
+
+/// Media type of RFC 7807 error bodies.
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Entry of the error catalogue served under `/problems`.
+#[derive(Debug, serde::Serialize)]
+pub struct ProblemType {
+    #[serde(rename = "type")]
+    pub type_uri: &'static str,
+    pub code: &'static str,
+    pub title: &'static str,
+    pub status: u16,
+    pub description: &'static str,
+}
+
+/// Every problem type the API can return, keyed by `Error::code`.
+pub const PROBLEM_TYPES: [ProblemType; 12] = [
+    ProblemType {
+        type_uri: "/problems/internal_error",
+        code: "internal_error",
+        title: "Internal server error",
+        status: 500,
+        description: "The server failed to process a valid request. Details are only logged.",
+    },
+    ProblemType {
+        type_uri: "/problems/invalid_input",
+        code: "invalid_input",
+        title: "Invalid input",
+        status: 400,
+        description: "The request body, path or headers failed validation.",
+    },
+    ProblemType {
+        type_uri: "/problems/authentication_required",
+        code: "authentication_required",
+        title: "Authentication required",
+        status: 401,
+        description:
+            "Credentials are missing or invalid. See WWW-Authenticate for accepted schemes.",
+    },
+    ProblemType {
+        type_uri: "/problems/permission_denied",
+        code: "permission_denied",
+        title: "Permission denied",
+        status: 403,
+        description: "The authenticated user is not allowed to perform this operation.",
+    },
+    ProblemType {
+        type_uri: "/problems/rate_limited",
+        code: "rate_limited",
+        title: "Too many requests",
+        status: 429,
+        description:
+            "The client or account is throttled. Retry after the number of seconds in Retry-After.",
+    },
+    ProblemType {
+        type_uri: "/problems/conflict",
+        code: "conflict",
+        title: "Conflict",
+        status: 409,
+        description: "The resource already exists or conflicts with the current state.",
+    },
+    ProblemType {
+        type_uri: "/problems/service_unavailable",
+        code: "service_unavailable",
+        title: "Service unavailable",
+        status: 503,
+        description: "The server is temporarily overloaded. The request may be retried later.",
+    },
+    ProblemType {
+        type_uri: "/problems/password_policy_violation",
+        code: "password_policy_violation",
+        title: "Password policy violation",
+        status: 400,
+        description: "The password was rejected. The `violations` member lists every failed rule.",
+    },
+    ProblemType {
+        type_uri: "/problems/unsupported_media_type",
+        code: "unsupported_media_type",
+        title: "Unsupported media type",
+        status: 415,
+        description: "Request bodies must be sent as `Content-Type: application/json`.",
+    },
+    ProblemType {
+        type_uri: "/problems/payload_too_large",
+        code: "payload_too_large",
+        title: "Payload too large",
+        status: 413,
+        description: "The request body exceeds the configured maximum size.",
+    },
+    ProblemType {
+        type_uri: "/problems/not_found",
+        code: "not_found",
+        title: "Not found",
+        status: 404,
+        description: "The requested resource does not exist.",
+    },
+    ProblemType {
+        type_uri: "/problems/method_not_allowed",
+        code: "method_not_allowed",
+        title: "Method not allowed",
+        status: 405,
+        description: "The resource does not support the method. See Allow for the supported ones.",
+    },
+];
+
+/// Looks up a catalogue entry by its code.
+pub fn problem_type(code: &str) -> Option<&'static ProblemType> {
+    PROBLEM_TYPES.iter().find(|problem| problem.code == code)
+}
+
+/// Whole seconds for `Retry-After` and similar headers, rounded up so clients
+/// never retry before a limit expires.
+pub fn ceil_secs(duration: std::time::Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+/// Problem body stashed in the response so `problem_details` can complete it.
+#[derive(Clone)]
+struct Problem(serde_json::Value);
+
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
+        let retry_after = match self {
+            Error::TooManyRequests(retry_after) => Some(ceil_secs(retry_after)),
+            _ => None,
+        };
         let (status, error_message) = match self {
             Error::ConfigurationError(ref _err) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -57,24 +283,139 @@ impl IntoResponse for Error {
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Database Query Error".to_string(),
             ),
+            Error::MigrationError(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Internal server error".to_string(),
+            ),
             Error::IllegalArgumentException(ref err) => {
                 (StatusCode::BAD_REQUEST, format!("Invalid input: {}", err))
             }
             Error::AuthenticationError(ref err) => {
-                (StatusCode::FORBIDDEN, format!("Forbidden: {}", err))
+                (StatusCode::UNAUTHORIZED, format!("Unauthorized: {}", err))
             }
-            Error::AuthorizationError(ref _err) => {
-                (StatusCode::UNAUTHORIZED, "Unauthorized".to_string())
+            Error::AuthorizationError(ref err) => {
+                (StatusCode::FORBIDDEN, format!("Forbidden: {}", err))
             }
             Error::ServerError(_) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 "Internal server error".to_string(),
             ),
+            Error::NotificationError(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Internal server error".to_string(),
+            ),
+            Error::CryptoError(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Internal server error".to_string(),
+            ),
+            Error::TooManyRequests(_) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "Too many requests".to_string(),
+            ),
+            Error::Conflict(ref err) => (StatusCode::CONFLICT, format!("Conflict: {}", err)),
+            Error::ServiceUnavailable(_) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Service unavailable".to_string(),
+            ),
+            Error::PolicyViolation(_) => (
+                StatusCode::BAD_REQUEST,
+                "Invalid input: password does not meet the policy".to_string(),
+            ),
+            Error::UnsupportedMediaType(ref err) => {
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, err.clone())
+            }
+            Error::PayloadTooLarge(ref err) => (StatusCode::PAYLOAD_TOO_LARGE, err.clone()),
+            Error::NotFound(ref err) => (StatusCode::NOT_FOUND, format!("Not found: {}", err)),
+            Error::MethodNotAllowed(ref method) => (
+                StatusCode::METHOD_NOT_ALLOWED,
+                format!("Method not allowed: {}", method),
+            ),
+        };
+        let code = self.code();
+        let (type_uri, title) = match problem_type(code) {
+            Some(problem) => (problem.type_uri, problem.title),
+            None => ("about:blank", status.canonical_reason().unwrap_or("Error")),
         };
-        let body = Json(json!({
-            "message": error_message,
-        }));
+        let mut body = json!({
+            "type": type_uri,
+            "title": title,
+            "status": status.as_u16(),
+            "detail": error_message,
+            "code": code,
+        });
+        if let Error::PolicyViolation(ref violations) = self {
+            body["violations"] = json!(violations);
+        }
+
+        let mut response = (
+            status,
+            [(header::CONTENT_TYPE, PROBLEM_JSON)],
+            Json(body.clone()),
+        )
+            .into_response();
+        response.extensions_mut().insert(Problem(body));
+        if let Some(seconds) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, header::HeaderValue::from(seconds));
+        }
+        if status == StatusCode::UNAUTHORIZED {
+            for challenge in AUTHENTICATE_CHALLENGES {
+                response.headers_mut().append(
+                    header::WWW_AUTHENTICATE,
+                    header::HeaderValue::from_static(challenge),
+                );
+            }
+        }
+        response
+    }
+}
 
-        (status, body).into_response()
+/// Fallback for requests no route matches.
+pub async fn route_not_found(uri: Uri) -> Error {
+    Error::NotFound(format!("No resource at {}", uri.path()))
+}
+
+/// Replaces the empty body of a 405 from the router with problem details,
+/// keeping the `Allow` header that lists the supported methods.
+pub async fn method_not_allowed<B>(request: Request<B>, next: Next<B>) -> Response {
+    let method = request.method().clone();
+    let response = next.run(request).await;
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED
+        || response.extensions().get::<Problem>().is_some()
+    {
+        return response;
     }
+
+    let mut problem = Error::MethodNotAllowed(method).into_response();
+    if let Some(allow) = response.headers().get(header::ALLOW) {
+        problem.headers_mut().insert(header::ALLOW, allow.clone());
+    }
+    problem
+}
+
+/// Completes problem bodies with the request path as `instance` and the
+/// correlation id clients should quote when reporting the failure.
+pub async fn problem_details<B>(request: Request<B>, next: Next<B>) -> Response {
+    let instance = request.uri().path().to_string();
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(|RequestId(request_id)| request_id.clone());
+
+    let response = next.run(request).await;
+    let Some(Problem(mut body)) = response.extensions().get::<Problem>().cloned() else {
+        return response;
+    };
+    body["instance"] = json!(instance);
+    if let Some(request_id) = request_id {
+        body["request_id"] = json!(request_id);
+    }
+
+    // The body is replaced, so drop the length and any encoding applied to
+    // the original one.
+    let (mut parts, _) = response.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.remove(header::CONTENT_ENCODING);
+    Response::from_parts(parts, boxed(Full::from(body.to_string())))
 }
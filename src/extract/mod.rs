@@ -0,0 +1,57 @@
+use axum::{
+    async_trait,
+    body::HttpBody,
+    extract::{FromRequest, FromRequestParts, Json, Path},
+    BoxError,
+};
+use hyper::{http::request::Parts, Request};
+use serde::de::DeserializeOwned;
+
+use crate::error::Error;
+
+/// JSON request body whose rejections are reported as problem details.
+///
+/// Behaves like `axum::Json`: the body must be sent as `application/json`
+/// and is capped by the `DefaultBodyLimit` layer. Request types are expected
+/// to use `#[serde(deny_unknown_fields)]` so typos are rejected rather than
+/// silently ignored.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonBody<T>(pub T);
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for JsonBody<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = Error;
+
+    async fn from_request(request: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(request, state).await?;
+        Ok(JsonBody(value))
+    }
+}
+
+/// Path parameters whose rejections are reported as problem details.
+///
+/// Behaves like `axum::extract::Path`, so validated types such as
+/// `Username` can be extracted directly from the URL.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PathParams<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for PathParams<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(value) = Path::<T>::from_request_parts(parts, state).await?;
+        Ok(PathParams(value))
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::Response};
+use hyper::header::{self, HeaderMap, HeaderName, HeaderValue};
+
+/// Security headers added to responses of one route group. Group policies
+/// are applied inside the default one, which only fills in headers a group
+/// did not set, so every response, errors included, carries a full set.
+#[derive(Debug, Clone)]
+pub struct SecurityHeaders {
+    headers: HeaderMap,
+}
+
+impl SecurityHeaders {
+    pub fn from_config(config: &crate::config::Config) -> Result<Self, crate::error::Error> {
+        let value = |value: &str, name: &str| {
+            HeaderValue::from_str(value)
+                .map_err(|_| crate::error::Error::ConfigurationError(String::from(name)))
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::X_CONTENT_TYPE_OPTIONS,
+            HeaderValue::from_static("nosniff"),
+        );
+        headers.insert(header::X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        headers.insert(
+            header::CONTENT_SECURITY_POLICY,
+            value(&config.content_security_policy, "CONTENT_SECURITY_POLICY")?,
+        );
+        headers.insert(
+            header::REFERRER_POLICY,
+            value(&config.referrer_policy, "REFERRER_POLICY")?,
+        );
+        headers.insert(
+            HeaderName::from_static("permissions-policy"),
+            value(&config.permissions_policy, "PERMISSIONS_POLICY")?,
+        );
+        headers.insert(
+            HeaderName::from_static("cross-origin-opener-policy"),
+            HeaderValue::from_static("same-origin"),
+        );
+        headers.insert(
+            HeaderName::from_static("cross-origin-embedder-policy"),
+            HeaderValue::from_static("require-corp"),
+        );
+        headers.insert(
+            HeaderName::from_static("cross-origin-resource-policy"),
+            HeaderValue::from_static("same-origin"),
+        );
+
+        // Browsers ignore HSTS received over plain HTTP, so the header is
+        // only sent when this server terminates TLS itself.
+        match config.hsts_max_age {
+            _ if config.tls_cert_file.is_none() => {}
+            0 => {}
+            max_age => {
+                let mut hsts = format!("max-age={}", max_age);
+                if config.hsts_include_subdomains {
+                    hsts.push_str("; includeSubDomains");
+                }
+                if config.hsts_preload {
+                    hsts.push_str("; preload");
+                }
+                headers.insert(
+                    header::STRICT_TRANSPORT_SECURITY,
+                    value(&hsts, "HSTS_MAX_AGE")?,
+                );
+            }
+        }
+
+        Ok(SecurityHeaders { headers })
+    }
+
+    /// Same policy with one header replaced, such as `Cache-Control` for
+    /// responses that are public and identical for every caller, or the
+    /// `Content-Security-Policy` of a group serving documents.
+    pub fn with(mut self, name: HeaderName, value: &'static str) -> Self {
+        self.headers.insert(name, HeaderValue::from_static(value));
+        self
+    }
+
+    fn apply(&self, headers: &mut HeaderMap) {
+        for (name, value) in &self.headers {
+            headers.entry(name).or_insert_with(|| value.clone());
+        }
+    }
+}
+
+pub async fn apply_policy<B>(
+    State(policy): State<Arc<SecurityHeaders>>,
+    mut response: Response<B>,
+) -> Response<B> {
+    policy.apply(response.headers_mut());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_headers_take_precedence_over_the_default() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        headers.insert(
+            header::CONTENT_SECURITY_POLICY,
+            HeaderValue::from_static("default-src 'none'"),
+        );
+        headers.insert(
+            header::REFERRER_POLICY,
+            HeaderValue::from_static("no-referrer"),
+        );
+        let default = SecurityHeaders { headers };
+        let group = default
+            .clone()
+            .with(header::CONTENT_SECURITY_POLICY, "default-src 'self'")
+            .with(
+                HeaderName::from_static("cross-origin-resource-policy"),
+                "cross-origin",
+            );
+
+        let mut response = HeaderMap::new();
+        group.apply(&mut response);
+        default.apply(&mut response);
+
+        assert_eq!(
+            response[header::CONTENT_SECURITY_POLICY],
+            "default-src 'self'"
+        );
+        assert_eq!(response["cross-origin-resource-policy"], "cross-origin");
+        assert_eq!(response[header::CACHE_CONTROL], "no-store");
+        assert_eq!(response[header::REFERRER_POLICY], "no-referrer");
+    }
+}
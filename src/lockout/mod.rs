@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Upper bound on tracked accounts or addresses, so that a flood of random
+/// usernames cannot exhaust memory.
+const MAX_TRACKED: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lockout {
+    Account,
+    Ip,
+}
+
+struct FailureRecord {
+    failures: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+struct FailureCounter<K> {
+    records: Mutex<HashMap<K, FailureRecord>>,
+    threshold: u32,
+}
+
+impl<K: Eq + Hash + Clone> FailureCounter<K> {
+    fn new(threshold: u32) -> Self {
+        FailureCounter {
+            records: Mutex::new(HashMap::new()),
+            threshold,
+        }
+    }
+
+    fn locked_for(&self, key: &K, now: Instant) -> Option<Duration> {
+        let records = self.records.lock().unwrap();
+        records
+            .get(key)
+            .and_then(|record| record.locked_until)
+            .filter(|locked_until| *locked_until > now)
+            .map(|locked_until| locked_until - now)
+    }
+
+    /// Returns `true` if this failure started a new lockout.
+    fn record_failure(&self, key: K, now: Instant, settings: &Settings) -> bool {
+        let mut records = self.records.lock().unwrap();
+        if records.len() >= MAX_TRACKED && !records.contains_key(&key) {
+            records.retain(|_, record| now - record.last_failure < settings.window);
+            // Still full of recent failures: forget the stalest record,
+            // sparing active lockouts for as long as possible.
+            if records.len() >= MAX_TRACKED {
+                let stalest = records
+                    .iter()
+                    .min_by_key(|(_, record)| {
+                        (
+                            record.locked_until.is_some_and(|until| until > now),
+                            record.last_failure,
+                        )
+                    })
+                    .map(|(key, _)| key.clone());
+                if let Some(stalest) = stalest {
+                    records.remove(&stalest);
+                }
+            }
+        }
+
+        let record = records.entry(key).or_insert(FailureRecord {
+            failures: 0,
+            last_failure: now,
+            locked_until: None,
+        });
+        if now - record.last_failure >= settings.window {
+            record.failures = 0;
+            record.locked_until = None;
+        }
+        record.failures += 1;
+        record.last_failure = now;
+
+        if record.failures < self.threshold {
+            return false;
+        }
+        let exponent = (record.failures - self.threshold).min(31);
+        let delay = settings
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(exponent))
+            .min(settings.max_delay);
+        record.locked_until = Some(now + delay);
+        true
+    }
+
+    fn reset(&self, key: &K) {
+        self.records.lock().unwrap().remove(key);
+    }
+}
+
+struct Settings {
+    base_delay: Duration,
+    max_delay: Duration,
+    window: Duration,
+}
+
+/// Counts failed logins per account and per client address. Once a threshold
+/// is reached, further attempts are refused for a delay that doubles with
+/// every additional failure. Accounts are keyed by the submitted username
+/// whether or not it exists, so lockouts do not reveal registered users.
+pub struct LoginThrottle {
+    accounts: FailureCounter<String>,
+    ips: FailureCounter<IpAddr>,
+    settings: Settings,
+}
+
+impl LoginThrottle {
+    pub fn from_config(config: &crate::config::Config) -> Self {
+        LoginThrottle {
+            accounts: FailureCounter::new(config.lockout_account_threshold),
+            ips: FailureCounter::new(config.lockout_ip_threshold),
+            settings: Settings {
+                base_delay: Duration::from_secs(config.lockout_base_delay),
+                max_delay: Duration::from_secs(config.lockout_max_delay),
+                window: Duration::from_secs(config.lockout_window),
+            },
+        }
+    }
+
+    /// Returns how long the caller must wait if the account or address is
+    /// currently locked.
+    pub fn check(&self, username: Option<&str>, ip: IpAddr) -> Option<Duration> {
+        let now = Instant::now();
+        let account_lock =
+            username.and_then(|username| self.accounts.locked_for(&username.to_string(), now));
+        let ip_lock = self.ips.locked_for(&ip, now);
+        account_lock.max(ip_lock)
+    }
+
+    /// Records a failed login and returns the lockouts it triggered.
+    pub fn record_failure(&self, username: Option<&str>, ip: IpAddr) -> Vec<Lockout> {
+        let now = Instant::now();
+        let mut lockouts = vec![];
+        if let Some(username) = username {
+            if self
+                .accounts
+                .record_failure(username.to_string(), now, &self.settings)
+            {
+                lockouts.push(Lockout::Account);
+            }
+        }
+        if self.ips.record_failure(ip, now, &self.settings) {
+            lockouts.push(Lockout::Ip);
+        }
+        lockouts
+    }
+
+    pub fn record_success(&self, username: &str) {
+        self.accounts.reset(&username.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SETTINGS: Settings = Settings {
+        base_delay: Duration::from_secs(1),
+        max_delay: Duration::from_secs(8),
+        window: Duration::from_secs(60),
+    };
+
+    #[test]
+    fn locks_out_at_the_threshold() {
+        let counter = FailureCounter::new(3);
+        let now = Instant::now();
+
+        assert!(!counter.record_failure("alice", now, &SETTINGS));
+        assert!(!counter.record_failure("alice", now, &SETTINGS));
+        assert_eq!(counter.locked_for(&"alice", now), None);
+
+        assert!(counter.record_failure("alice", now, &SETTINGS));
+        assert_eq!(counter.locked_for(&"alice", now), Some(SETTINGS.base_delay));
+        assert_eq!(counter.locked_for(&"bob", now), None);
+        assert_eq!(
+            counter.locked_for(&"alice", now + SETTINGS.base_delay),
+            None
+        );
+    }
+
+    #[test]
+    fn delay_doubles_up_to_the_maximum() {
+        let counter = FailureCounter::new(1);
+        let now = Instant::now();
+
+        for expected in [1, 2, 4, 8, 8] {
+            assert!(counter.record_failure("alice", now, &SETTINGS));
+            assert_eq!(
+                counter.locked_for(&"alice", now),
+                Some(Duration::from_secs(expected))
+            );
+        }
+    }
+
+    #[test]
+    fn failures_outside_the_window_start_over() {
+        let counter = FailureCounter::new(2);
+        let now = Instant::now();
+
+        assert!(!counter.record_failure("alice", now, &SETTINGS));
+        assert!(counter.record_failure("alice", now, &SETTINGS));
+        let later = now + SETTINGS.window;
+        assert!(!counter.record_failure("alice", later, &SETTINGS));
+        assert_eq!(counter.locked_for(&"alice", later), None);
+
+        counter.reset(&"alice");
+        assert!(!counter.record_failure("alice", later, &SETTINGS));
+    }
+
+    #[test]
+    fn evicts_the_stalest_record_but_spares_lockouts() {
+        let counter = FailureCounter::new(2);
+        let now = Instant::now();
+
+        assert!(!counter.record_failure(0, now, &SETTINGS));
+        assert!(counter.record_failure(0, now, &SETTINGS));
+        let stalest = 1;
+        counter.record_failure(stalest, now, &SETTINGS);
+        for key in 2..MAX_TRACKED {
+            counter.record_failure(key, now + Duration::from_millis(1), &SETTINGS);
+        }
+
+        counter.record_failure(MAX_TRACKED, now + Duration::from_millis(2), &SETTINGS);
+        let records = counter.records.lock().unwrap();
+        assert_eq!(records.len(), MAX_TRACKED);
+        assert!(records.contains_key(&0), "active lockout was evicted");
+        assert!(!records.contains_key(&stalest));
+        assert!(records.contains_key(&MAX_TRACKED));
+    }
+}
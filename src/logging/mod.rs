@@ -0,0 +1,76 @@
+use std::path::Path;
+
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{
+    fmt::writer::BoxMakeWriter, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer,
+};
+
+/// Installs the global subscriber described by the config. The returned
+/// guard flushes buffered file output and must live as long as the server.
+pub fn init(config: &crate::config::Config) -> Result<Option<WorkerGuard>, crate::error::Error> {
+    let log_filter = match EnvFilter::try_from_default_env() {
+        Ok(filter) => filter,
+        Err(_) => EnvFilter::try_new(format!("api_sec_natter={}", config.log_level))
+            .map_err(|_| crate::error::Error::ConfigurationError(String::from("LOG_LEVEL")))?,
+    };
+
+    let (writer, guard) = match config.log_file {
+        Some(ref log_file) => {
+            let (writer, guard) =
+                tracing_appender::non_blocking(file_appender(log_file, &config.log_rotation)?);
+            (BoxMakeWriter::new(writer), Some(guard))
+        }
+        None => (BoxMakeWriter::new(std::io::stdout), None),
+    };
+
+    let fmt_layer = tracing_subscriber::fmt::layer().with_writer(writer);
+    let fmt_layer = match config.log_format.as_str() {
+        "json" => fmt_layer
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed(),
+        "pretty" => fmt_layer.pretty().boxed(),
+        _ => {
+            return Err(crate::error::Error::ConfigurationError(String::from(
+                "LOG_FORMAT",
+            )))
+        }
+    };
+
+    tracing_subscriber::registry()
+        .with(fmt_layer)
+        .with(log_filter)
+        .try_init()
+        .map_err(|_| crate::error::Error::ConfigurationError(String::from("tracing subscriber")))?;
+    Ok(guard)
+}
+
+fn file_appender(
+    log_file: &str,
+    rotation: &str,
+) -> Result<RollingFileAppender, crate::error::Error> {
+    let rotation = match rotation {
+        "minutely" => Rotation::MINUTELY,
+        "hourly" => Rotation::HOURLY,
+        "daily" => Rotation::DAILY,
+        "never" => Rotation::NEVER,
+        _ => {
+            return Err(crate::error::Error::ConfigurationError(String::from(
+                "LOG_ROTATION",
+            )))
+        }
+    };
+    let path = Path::new(log_file);
+    let directory = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| crate::error::Error::ConfigurationError(String::from("LOG_FILE")))?;
+    Ok(RollingFileAppender::new(rotation, directory, file_name))
+}
@@ -0,0 +1,207 @@
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    BoxError, Extension,
+};
+use hyper::{header, Request, StatusCode};
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+
+use crate::password::PasswordHasher;
+
+/// Process-wide collectors. Counters are updated where the events happen;
+/// gauges describing shared resources are sampled when scraped.
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    auth_attempts: IntCounterVec,
+    argon2_verify_duration: Histogram,
+    service_rejections: IntCounterVec,
+    db_pool_connections: IntGauge,
+    db_pool_idle: IntGauge,
+    hasher_in_flight: IntGauge,
+    hasher_queued: IntGauge,
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+pub fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(|| Metrics::new().expect("metrics::invalid collector definition"))
+}
+
+impl Metrics {
+    fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some(String::from("natter")), None)?;
+
+        let http_requests = IntCounterVec::new(
+            Opts::new(
+                "http_requests_total",
+                "HTTP requests by route, method and status",
+            ),
+            &["route", "method", "status"],
+        )?;
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "HTTP request latency by route, method and status",
+            ),
+            &["route", "method", "status"],
+        )?;
+        let auth_attempts = IntCounterVec::new(
+            Opts::new(
+                "auth_attempts_total",
+                "Authentication attempts by mechanism and outcome",
+            ),
+            &["mechanism", "outcome"],
+        )?;
+        let argon2_verify_duration = Histogram::with_opts(
+            HistogramOpts::new(
+                "argon2_verify_duration_seconds",
+                "Time spent verifying a password hash on the blocking pool",
+            )
+            .buckets(vec![0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5]),
+        )?;
+        let service_rejections = IntCounterVec::new(
+            Opts::new(
+                "service_rejections_total",
+                "Requests rejected by rate limits or the service stack before reaching a handler",
+            ),
+            &["reason"],
+        )?;
+        let db_pool_connections = IntGauge::new(
+            "db_api_pool_connections",
+            "Connections currently open in the API database pool",
+        )?;
+        let db_pool_idle = IntGauge::new(
+            "db_api_pool_idle_connections",
+            "Idle connections in the API database pool",
+        )?;
+        let hasher_in_flight = IntGauge::new(
+            "password_hasher_in_flight",
+            "Password hashing jobs running on the blocking pool",
+        )?;
+        let hasher_queued = IntGauge::new(
+            "password_hasher_queued",
+            "Password hashing jobs waiting for a worker",
+        )?;
+
+        registry.register(Box::new(http_requests.clone()))?;
+        registry.register(Box::new(http_request_duration.clone()))?;
+        registry.register(Box::new(auth_attempts.clone()))?;
+        registry.register(Box::new(argon2_verify_duration.clone()))?;
+        registry.register(Box::new(service_rejections.clone()))?;
+        registry.register(Box::new(db_pool_connections.clone()))?;
+        registry.register(Box::new(db_pool_idle.clone()))?;
+        registry.register(Box::new(hasher_in_flight.clone()))?;
+        registry.register(Box::new(hasher_queued.clone()))?;
+
+        Ok(Metrics {
+            registry,
+            http_requests,
+            http_request_duration,
+            auth_attempts,
+            argon2_verify_duration,
+            service_rejections,
+            db_pool_connections,
+            db_pool_idle,
+            hasher_in_flight,
+            hasher_queued,
+        })
+    }
+
+    pub fn record_auth(&self, mechanism: &str, success: bool) {
+        let outcome = if success { "success" } else { "failure" };
+        self.auth_attempts
+            .with_label_values(&[mechanism, outcome])
+            .inc();
+    }
+
+    pub fn record_rejection(&self, reason: &str) {
+        self.service_rejections.with_label_values(&[reason]).inc();
+    }
+
+    pub fn start_argon2_timer(&self) -> prometheus::HistogramTimer {
+        self.argon2_verify_duration.start_timer()
+    }
+}
+
+/// Counts every routed request and observes its latency. Routes are
+/// labelled with their pattern so path parameters do not blow up cardinality.
+pub async fn track_requests<B>(request: Request<B>, next: Next<B>) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| String::from("unmatched"));
+    let method = request.method().to_string();
+    let start = Instant::now();
+
+    let response = next.run(request).await;
+
+    let status = response.status().as_u16().to_string();
+    let labels = [route.as_str(), method.as_str(), status.as_str()];
+    let metrics = metrics();
+    metrics.http_requests.with_label_values(&labels).inc();
+    metrics
+        .http_request_duration
+        .with_label_values(&labels)
+        .observe(start.elapsed().as_secs_f64());
+    response
+}
+
+/// Turns failures of the outer service stack into responses, counting why
+/// the request never reached the router.
+pub async fn handle_service_error(error: BoxError) -> Response {
+    let (reason, error) = if error.is::<tower::timeout::error::Elapsed>() {
+        (
+            "timeout",
+            crate::error::Error::ServiceUnavailable(String::from("Request timed out")),
+        )
+    } else if error.is::<tower::load_shed::error::Overloaded>() {
+        (
+            "overloaded",
+            crate::error::Error::ServiceUnavailable(String::from("Server is overloaded")),
+        )
+    } else {
+        tracing::event!(tracing::Level::ERROR, "metrics::service error {:?}", error);
+        (
+            "internal",
+            crate::error::Error::ServiceUnavailable(error.to_string()),
+        )
+    };
+    metrics().record_rejection(reason);
+    error.into_response()
+}
+
+pub async fn render(
+    State(store): State<Arc<crate::store::Store>>,
+    Extension(hasher): Extension<Arc<PasswordHasher>>,
+) -> impl IntoResponse {
+    let metrics = metrics();
+    metrics
+        .db_pool_connections
+        .set(i64::from(store.connection.size()));
+    metrics.db_pool_idle.set(store.connection.num_idle() as i64);
+    let stats = hasher.stats();
+    metrics.hasher_in_flight.set(stats.in_flight as i64);
+    metrics.hasher_queued.set(stats.queued as i64);
+
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if let Err(e) = encoder.encode(&metrics.registry.gather(), &mut buffer) {
+        tracing::event!(tracing::Level::ERROR, "metrics::render {:?}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    (
+        [(header::CONTENT_TYPE, encoder.format_type().to_string())],
+        buffer,
+    )
+        .into_response()
+}
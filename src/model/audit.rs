@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditEvent {
+    pub method: String,
+    pub path: String,
+    pub user_id: Option<String>,
+    pub status: Option<i32>,
+    pub event: Option<String>,
+    pub client_ip: Option<String>,
+    pub request_id: Option<String>,
+}
@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+use crate::validation::{GroupName, Username};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Group {
+    pub group_id: Option<GroupId>,
+    pub name: String,
+    pub owner: String,
+}
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GroupId(pub i64);
+
+/// Request body of `POST /groups`; the id is always assigned by the store.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct NewGroup {
+    pub name: GroupName,
+    pub owner: Username,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewGroupCreated {
+    pub name: String,
+    pub uri: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct NewGroupMember {
+    pub username: Username,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewGroupMemberCreated {
+    pub username: String,
+    pub uri: String,
+}
@@ -1,11 +1,14 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::validation::{MessageText, Username};
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct NewMessage {
     pub space_id: crate::model::space::SpaceId,
-    pub author: String,
-    pub msg_text: String,
+    pub author: Username,
+    pub msg_text: MessageText,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -1,3 +1,6 @@
+pub mod audit;
+pub mod group;
 pub mod message;
 pub mod space;
 pub mod user;
+pub mod webauthn;
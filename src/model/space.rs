@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::validation::{Permissions, SpaceName, Username};
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Space {
     pub space_id: Option<SpaceId>,
@@ -9,8 +11,31 @@ pub struct Space {
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct SpaceId(pub i64);
 
+/// Request body of `POST /spaces`; the id is always assigned by the store.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct NewSpace {
+    pub name: SpaceName,
+    pub owner: Username,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NewSpaceCreated {
     pub name: String,
     pub uri: String,
 }
+
+/// Grants permissions on a space either to a single user or to a whole group.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct NewSpaceMember {
+    pub username: Option<Username>,
+    pub group_id: Option<crate::model::group::GroupId>,
+    pub permissions: Permissions,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewSpaceMemberCreated {
+    pub uri: String,
+    pub permissions: Permissions,
+}
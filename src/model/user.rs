@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -7,8 +8,9 @@ pub struct User {
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct NewUser {
-    pub username: String,
+    pub username: crate::validation::Username,
     pub password: String,
 }
 
@@ -19,8 +21,81 @@ pub struct NewUserCreated {
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PasswordChange {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PasswordReset {
+    pub token: String,
+    pub new_password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MfaCredential {
+    pub user_id: String,
+    pub secret: String,
+    pub confirmed: bool,
+    pub last_used_step: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MfaEnrollment {
+    pub otpauth_uri: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MfaConfirmation {
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MfaConfirmed {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Token {
+    pub token: String,
+    pub expiry: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cnf: Option<TokenConfirmation>,
+}
+
+/// RFC 8705 confirmation claim of a certificate-bound token.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TokenConfirmation {
+    #[serde(rename = "x5t#S256")]
+    pub x5t_s256: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct BearerToken {
+    pub user_id: String,
+    pub x5t_s256: Option<String>,
+}
+
+/// Credentials for `POST /users/login`. The one-time code is required for
+/// users with MFA enabled.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PasswordLogin {
+    pub username: crate::validation::Username,
+    pub password: String,
+    pub otp: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Session {
     pub username: Option<String>,
+    /// Whether a one-time code was verified, and thereby used up, while
+    /// authenticating this request.
+    #[serde(default)]
+    pub second_factor_verified: bool,
 }
 
 impl Session {
@@ -39,4 +114,13 @@ impl Session {
             ))),
         }
     }
+
+    pub fn get_username_or_error(&self, message: &str) -> Result<&str, crate::error::Error> {
+        match &self.username {
+            Some(username) => Ok(username),
+            None => Err(crate::error::Error::AuthenticationError(String::from(
+                message,
+            ))),
+        }
+    }
 }
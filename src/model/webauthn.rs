@@ -0,0 +1,102 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WebauthnCredential {
+    pub credential_id: String,
+    pub user_id: String,
+    pub public_key: Vec<u8>,
+    pub sign_count: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RelyingPartyEntity {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UserEntity {
+    pub id: String,
+    pub name: String,
+    pub display_name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CredentialParameters {
+    #[serde(rename = "type")]
+    pub credential_type: String,
+    pub alg: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CredentialDescriptor {
+    #[serde(rename = "type")]
+    pub credential_type: String,
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialCreationOptions {
+    pub challenge: String,
+    pub rp: RelyingPartyEntity,
+    pub user: UserEntity,
+    pub pub_key_cred_params: Vec<CredentialParameters>,
+    pub exclude_credentials: Vec<CredentialDescriptor>,
+    pub authenticator_selection: AuthenticatorSelection,
+    pub timeout: u64,
+    pub attestation: String,
+}
+
+/// Logins only offer discoverable credentials, so registration requires them.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorSelection {
+    pub resident_key: String,
+    pub require_resident_key: bool,
+    pub user_verification: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialRequestOptions {
+    pub challenge: String,
+    pub rp_id: String,
+    pub allow_credentials: Vec<CredentialDescriptor>,
+    pub timeout: u64,
+    pub user_verification: String,
+}
+
+/// Result of `navigator.credentials.create()`, binary fields base64url encoded.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct NewWebauthnCredential {
+    pub id: String,
+    pub client_data_json: String,
+    pub attestation_object: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewWebauthnCredentialCreated {
+    pub credential_id: String,
+    pub uri: String,
+}
+
+/// Body of `POST /webauthn/login/challenge`. The username is accepted for
+/// compatibility but ignored, as the challenge never depends on the user.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WebauthnLoginRequest {
+    pub username: Option<crate::validation::Username>,
+}
+
+/// Result of `navigator.credentials.get()`, binary fields base64url encoded.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WebauthnAssertion {
+    pub id: String,
+    pub client_data_json: String,
+    pub authenticator_data: String,
+    pub signature: String,
+}
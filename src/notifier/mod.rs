@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use axum::async_trait;
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+
+/// Delivers out-of-band messages, such as password reset tokens, to users.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn send_password_reset(
+        &self,
+        user_id: &str,
+        token: &str,
+    ) -> Result<(), crate::error::Error>;
+}
+
+/// Prints notifications to stdout, which is also where logs go. Intended for
+/// local testing only and selected with `RESET_NOTIFIER=console`.
+pub struct ConsoleNotifier;
+
+#[async_trait]
+impl Notifier for ConsoleNotifier {
+    async fn send_password_reset(
+        &self,
+        user_id: &str,
+        token: &str,
+    ) -> Result<(), crate::error::Error> {
+        println!("Password reset token for {}: {}", user_id, token);
+        Ok(())
+    }
+}
+
+/// Appends notifications to a file. Intended for local testing only and
+/// selected with `RESET_NOTIFIER=file` and `RESET_NOTIFIER_FILE`.
+pub struct FileNotifier {
+    path: String,
+}
+
+impl FileNotifier {
+    pub fn new(path: &str) -> Self {
+        FileNotifier {
+            path: path.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for FileNotifier {
+    async fn send_password_reset(
+        &self,
+        user_id: &str,
+        token: &str,
+    ) -> Result<(), crate::error::Error> {
+        let line = format!("Password reset token for {}: {}\n", user_id, token);
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(|e| crate::error::Error::NotificationError(e.to_string()))?
+            .write_all(line.as_bytes())
+            .await
+            .map_err(|e| crate::error::Error::NotificationError(e.to_string()))
+    }
+}
+
+/// Returns `None` when `RESET_NOTIFIER` is unset, in which case password
+/// resets are disabled.
+pub fn from_config(
+    config: &crate::config::Config,
+) -> Result<Option<Arc<dyn Notifier>>, crate::error::Error> {
+    match (
+        config.reset_notifier.as_deref(),
+        config.reset_notifier_file.as_deref(),
+    ) {
+        (None, _) => Ok(None),
+        (Some("console"), _) => Ok(Some(Arc::new(ConsoleNotifier))),
+        (Some("file"), Some(path)) => Ok(Some(Arc::new(FileNotifier::new(path)))),
+        _ => Err(crate::error::Error::ConfigurationError(String::from(
+            "RESET_NOTIFIER",
+        ))),
+    }
+}
@@ -0,0 +1,310 @@
+pub mod policy;
+
+use argon2::{Config, Variant, Version};
+use rand::Rng;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+/// Upper bound on the time cost picked by calibration.
+const MAX_CALIBRATED_TIME_COST: u32 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Argon2Params {
+    pub mem_cost: u32,
+    pub time_cost: u32,
+    pub parallelism: u32,
+}
+
+impl Argon2Params {
+    pub fn from_config(config: &crate::config::Config) -> Self {
+        Argon2Params {
+            mem_cost: config.argon2_mem_cost,
+            time_cost: config.argon2_time_cost,
+            parallelism: config.argon2_parallelism,
+        }
+    }
+
+    fn to_argon2_config(self) -> Config<'static> {
+        Config {
+            variant: Variant::Argon2id,
+            version: Version::Version13,
+            mem_cost: self.mem_cost,
+            time_cost: self.time_cost,
+            lanes: self.parallelism,
+            ..Config::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolSettings {
+    pub workers: usize,
+    pub max_queue: usize,
+}
+
+impl PoolSettings {
+    pub fn from_config(config: &crate::config::Config) -> Self {
+        PoolSettings {
+            workers: config.hash_workers,
+            max_queue: config.hash_queue_depth,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HasherStats {
+    pub in_flight: usize,
+    pub queued: usize,
+}
+
+/// Hashes and verifies passwords with Argon2id using the configured costs.
+///
+/// Argon2 is deliberately slow, so the work runs on tokio's blocking thread
+/// pool. At most `workers` hashes run at once and at most `max_queue` callers
+/// wait for a worker; beyond that requests fail fast with 503 instead of
+/// piling up.
+pub struct PasswordHasher {
+    params: Argon2Params,
+    dummy_hash: String,
+    workers: usize,
+    max_queue: usize,
+    permits: Arc<Semaphore>,
+    queued: AtomicUsize,
+}
+
+/// Keeps the queue depth accurate even if the waiting request is dropped.
+struct QueueSlot<'a>(&'a AtomicUsize);
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl PasswordHasher {
+    pub fn new(params: Argon2Params, pool: PoolSettings) -> Result<Self, crate::error::Error> {
+        Ok(PasswordHasher {
+            params,
+            dummy_hash: hash_blocking(params, &rand::thread_rng().gen::<[u8; 16]>())?,
+            workers: pool.workers,
+            max_queue: pool.max_queue,
+            permits: Arc::new(Semaphore::new(pool.workers)),
+            queued: AtomicUsize::new(0),
+        })
+    }
+
+    /// Raises the time cost until verifying a single password takes at least
+    /// `target`. Calibration starts from the configured costs and never
+    /// lowers them; memory and parallelism stay as configured.
+    pub fn calibrated(
+        params: Argon2Params,
+        pool: PoolSettings,
+        target: Duration,
+    ) -> Result<Self, crate::error::Error> {
+        let mut params = params;
+        loop {
+            let hasher = PasswordHasher::new(params, pool)?;
+            let start = Instant::now();
+            verify_blocking(&hasher.dummy_hash, b"calibration")?;
+            let elapsed = start.elapsed();
+
+            if elapsed >= target || params.time_cost >= MAX_CALIBRATED_TIME_COST {
+                tracing::event!(
+                    tracing::Level::INFO,
+                    "password::calibrated {:?} in {:?}",
+                    params,
+                    elapsed
+                );
+                return Ok(hasher);
+            }
+            params.time_cost += 1;
+        }
+    }
+
+    pub async fn hash(&self, password: &[u8]) -> Result<String, crate::error::Error> {
+        let params = self.params;
+        let password = password.to_vec();
+        self.run(move || hash_blocking(params, &password)).await?
+    }
+
+    pub async fn verify(&self, hash: &str, password: &[u8]) -> Result<bool, crate::error::Error> {
+        let hash = hash.to_string();
+        let password = password.to_vec();
+        self.run(move || {
+            let _timer = crate::metrics::metrics().start_argon2_timer();
+            verify_blocking(&hash, &password)
+        })
+        .await?
+    }
+
+    /// Spends the same effort as a real verification without any stored
+    /// hash, so unknown users cannot be told apart by response timing.
+    pub async fn verify_dummy(&self, password: &[u8]) -> Result<(), crate::error::Error> {
+        self.verify(&self.dummy_hash, password).await.map(|_| ())
+    }
+
+    pub fn stats(&self) -> HasherStats {
+        HasherStats {
+            in_flight: self.workers - self.permits.available_permits(),
+            queued: self.queued.load(Ordering::SeqCst),
+        }
+    }
+
+    async fn run<T, F>(&self, job: F) -> Result<T, crate::error::Error>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let permit = match self.permits.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                let waiting = self.queued.fetch_add(1, Ordering::SeqCst);
+                let slot = QueueSlot(&self.queued);
+                if waiting >= self.max_queue {
+                    tracing::event!(
+                        tracing::Level::WARN,
+                        "password::saturated {:?}",
+                        self.stats()
+                    );
+                    return Err(crate::error::Error::ServiceUnavailable(String::from(
+                        "Password hashing queue is full",
+                    )));
+                }
+                tracing::event!(tracing::Level::DEBUG, "password::queued {:?}", self.stats());
+                let permit = self.permits.clone().acquire_owned().await;
+                drop(slot);
+                permit.map_err(|e| crate::error::Error::CryptoError(e.to_string()))?
+            }
+        };
+
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            job()
+        })
+        .await
+        .map_err(|e| crate::error::Error::CryptoError(e.to_string()))
+    }
+
+    /// Returns `true` if the hash was produced with another variant or with
+    /// lower costs than currently configured.
+    pub fn needs_rehash(&self, hash: &str) -> bool {
+        match parse_encoded_params(hash) {
+            Some((variant, params)) => {
+                variant != Variant::Argon2id.as_lowercase_str()
+                    || params.mem_cost < self.params.mem_cost
+                    || params.time_cost < self.params.time_cost
+                    || params.parallelism < self.params.parallelism
+            }
+            None => true,
+        }
+    }
+}
+
+fn hash_blocking(params: Argon2Params, password: &[u8]) -> Result<String, crate::error::Error> {
+    let salt = rand::thread_rng().gen::<[u8; 32]>();
+    argon2::hash_encoded(password, &salt, &params.to_argon2_config())
+        .map_err(|e| crate::error::Error::CryptoError(e.to_string()))
+}
+
+fn verify_blocking(hash: &str, password: &[u8]) -> Result<bool, crate::error::Error> {
+    match argon2::verify_encoded(hash, password) {
+        Ok(verified) => Ok(verified),
+        // A mismatch is `Ok(false)`; this is a stored hash that cannot be
+        // parsed.
+        Err(_) => Err(crate::error::Error::CryptoError(String::from(
+            "Invalid password hash",
+        ))),
+    }
+}
+
+/// Extracts the variant and costs from a PHC string such as
+/// `$argon2i$v=19$m=4096,t=3,p=1$<salt>$<hash>`.
+fn parse_encoded_params(hash: &str) -> Option<(&str, Argon2Params)> {
+    let mut parts = hash.split('$').skip(1);
+    let variant = parts.next()?;
+    let mut costs = parts.find(|part| part.starts_with("m="))?.split(',');
+    let mut cost = |name: &str| {
+        costs
+            .next()?
+            .strip_prefix(name)?
+            .strip_prefix('=')?
+            .parse::<u32>()
+            .ok()
+    };
+    Some((
+        variant,
+        Argon2Params {
+            mem_cost: cost("m")?,
+            time_cost: cost("t")?,
+            parallelism: cost("p")?,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAMS: Argon2Params = Argon2Params {
+        mem_cost: 2048,
+        time_cost: 2,
+        parallelism: 1,
+    };
+    const POOL: PoolSettings = PoolSettings {
+        workers: 1,
+        max_queue: 8,
+    };
+    const SAMPLES: usize = 15;
+
+    fn median(mut samples: Vec<Duration>) -> Duration {
+        samples.sort();
+        samples[samples.len() / 2]
+    }
+
+    #[test]
+    fn calibration_never_lowers_the_time_cost() {
+        let hasher = PasswordHasher::calibrated(PARAMS, POOL, Duration::ZERO).unwrap();
+        assert_eq!(hasher.params, PARAMS);
+    }
+
+    #[test]
+    fn dummy_hash_uses_configured_params() {
+        let hasher = PasswordHasher::new(PARAMS, POOL).unwrap();
+        assert_eq!(
+            parse_encoded_params(&hasher.dummy_hash),
+            Some(("argon2id", PARAMS))
+        );
+        assert!(!hasher.needs_rehash(&hasher.dummy_hash));
+    }
+
+    /// A wrong password for a known user and any password for an unknown one
+    /// must cost the same, otherwise login timing reveals which users exist.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn known_and_unknown_users_take_the_same_time() {
+        let hasher = PasswordHasher::new(PARAMS, POOL).unwrap();
+        let stored = hasher.hash(b"correct horse").await.unwrap();
+
+        let mut known = Vec::with_capacity(SAMPLES);
+        let mut unknown = Vec::with_capacity(SAMPLES);
+        for _ in 0..SAMPLES {
+            let start = Instant::now();
+            assert!(!hasher.verify(&stored, b"battery staple").await.unwrap());
+            known.push(start.elapsed());
+
+            let start = Instant::now();
+            hasher.verify_dummy(b"battery staple").await.unwrap();
+            unknown.push(start.elapsed());
+        }
+
+        let (known, unknown) = (median(known), median(unknown));
+        let ratio = known.as_secs_f64() / unknown.as_secs_f64();
+        assert!(
+            (0.75..=1.33).contains(&ratio),
+            "known {:?} vs unknown {:?}",
+            known,
+            unknown
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use sha1::{Digest, Sha1};
+use std::path::PathBuf;
+
+/// Password rules in the spirit of NIST SP 800-63B: length limits, no
+/// composition rules, and rejection of known-breached passwords.
+pub struct PasswordPolicy {
+    min_length: usize,
+    max_length: usize,
+    breached_dir: Option<PathBuf>,
+}
+
+impl PasswordPolicy {
+    pub fn from_config(config: &crate::config::Config) -> Self {
+        PasswordPolicy {
+            min_length: config.password_min_length,
+            max_length: config.password_max_length,
+            breached_dir: config.breached_passwords_dir.as_ref().map(PathBuf::from),
+        }
+    }
+
+    /// Checks every rule and reports all that failed at once.
+    pub async fn check(&self, username: &str, password: &str) -> Result<(), crate::error::Error> {
+        let mut violations = vec![];
+
+        let length = password.chars().count();
+        if length < self.min_length {
+            violations.push(format!(
+                "Password too short. Use at least {} characters",
+                self.min_length
+            ));
+        }
+        if length > self.max_length {
+            violations.push(format!(
+                "Password too long. Use at most {} characters",
+                self.max_length
+            ));
+        }
+        if !username.is_empty() && password.to_lowercase().contains(&username.to_lowercase()) {
+            violations.push(String::from("Password must not contain the username"));
+        }
+        if self.is_breached(password).await? {
+            violations.push(String::from(
+                "Password appears in a list of breached passwords",
+            ));
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(crate::error::Error::PolicyViolation(violations))
+        }
+    }
+
+    /// Looks the password up in a directory of range files named after the
+    /// first five hex digits of its SHA-1 hash, each line holding the
+    /// remaining 35 digits and a count (the Pwned Passwords range format).
+    async fn is_breached(&self, password: &str) -> Result<bool, crate::error::Error> {
+        let dir = match &self.breached_dir {
+            Some(dir) => dir,
+            None => return Ok(false),
+        };
+        let hash: String = Sha1::digest(password.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        let (prefix, suffix) = hash.split_at(5);
+
+        for file_name in [prefix.to_string(), format!("{}.txt", prefix)] {
+            match tokio::fs::read_to_string(dir.join(file_name)).await {
+                Ok(range) => {
+                    return Ok(range.lines().any(|line| match line.trim().split_once(':') {
+                        Some((candidate, count)) => {
+                            candidate.eq_ignore_ascii_case(suffix)
+                                && count.trim().parse::<u64>().map_or(true, |count| count > 0)
+                        }
+                        None => line.trim().eq_ignore_ascii_case(suffix),
+                    }))
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    tracing::event!(tracing::Level::ERROR, "password::policy {:?}", e);
+                    return Err(crate::error::Error::ConfigurationError(String::from(
+                        "BREACHED_PASSWORDS_DIR",
+                    )));
+                }
+            }
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(breached_dir: Option<PathBuf>) -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 8,
+            max_length: 16,
+            breached_dir,
+        }
+    }
+
+    async fn violations(policy: &PasswordPolicy, username: &str, password: &str) -> Vec<String> {
+        match policy.check(username, password).await {
+            Ok(()) => vec![],
+            Err(crate::error::Error::PolicyViolation(violations)) => violations,
+            Err(e) => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn checks_length_in_characters() {
+        let policy = policy(None);
+        assert!(violations(&policy, "alice", "correcthorse")
+            .await
+            .is_empty());
+        assert_eq!(
+            violations(&policy, "alice", "short").await,
+            ["Password too short. Use at least 8 characters"]
+        );
+        assert_eq!(
+            violations(&policy, "alice", "much too long a password").await,
+            ["Password too long. Use at most 16 characters"]
+        );
+        // Eight characters, but more than eight bytes.
+        assert!(violations(&policy, "alice", "pässwört").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejects_the_username_in_any_case() {
+        let policy = policy(None);
+        assert_eq!(
+            violations(&policy, "alice", "xxALICExx").await,
+            ["Password must not contain the username"]
+        );
+        assert!(violations(&policy, "", "xxALICExx").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_every_violation() {
+        let policy = policy(None);
+        assert_eq!(violations(&policy, "bob", "bob").await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn looks_up_breached_passwords_in_range_files() {
+        let dir = std::env::temp_dir().join(format!("natter-breached-{}", rand::random::<u64>()));
+        std::fs::create_dir(&dir).unwrap();
+        // SHA-1 of "password" is 5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8,
+        // of "letmein1" it is D04C1675B232C6ECE69ED95E189E95D589F217B0 and
+        // of "qwerty123" it is 5CEC175B165E3D5E62C9E13CE848EF6FEAC81BFF.
+        std::fs::write(
+            dir.join("5BAA6.txt"),
+            "0018A45C4D1DEF81644B54AB7F969B88D65:1\r\n\
+             1e4c9b93f3f0682250b6cf8331b7ee68fd8:3730471\r\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("5CEC1"), "75B165E3D5E62C9E13CE848EF6FEAC81BFF:0\n").unwrap();
+
+        let policy = policy(Some(dir.clone()));
+        let breached = policy.is_breached("password").await;
+        let padding = policy.is_breached("qwerty123").await;
+        let missing_range = policy.is_breached("letmein1").await;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(breached.unwrap());
+        // Padding entries with a zero count are not breaches.
+        assert!(!padding.unwrap());
+        assert!(!missing_range.unwrap());
+    }
+}
@@ -0,0 +1,315 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{ConnectInfo, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use hyper::{
+    header::{HeaderMap, HeaderValue},
+    Request,
+};
+
+use crate::model::user::Session;
+
+/// Upper bound on tracked buckets, so that a flood of spoofed addresses
+/// cannot exhaust memory.
+const MAX_TRACKED: usize = 100_000;
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// Groups of routes that share limits.
+#[derive(Debug, Clone, Copy)]
+pub enum RouteClass {
+    /// Registration, password and WebAuthn login endpoints that handle
+    /// credentials and are attractive for guessing.
+    Auth,
+    /// Everything that requires an authenticated session.
+    Api,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    User(String),
+    Ip(IpAddr),
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// State of the bucket that admitted or refused a request.
+#[derive(Debug, Clone, Copy)]
+struct Quota {
+    limit: u32,
+    remaining: u32,
+    reset: Duration,
+    retry_after: Option<Duration>,
+}
+
+impl Quota {
+    /// Advertises this quota unless an inner limiter already reported a more
+    /// restrictive one.
+    fn apply(&self, headers: &mut HeaderMap) {
+        let tighter = headers
+            .get("RateLimit-Remaining")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u32>().ok())
+            .is_some_and(|remaining| remaining <= self.remaining);
+        if tighter {
+            return;
+        }
+        headers.insert("RateLimit-Limit", HeaderValue::from(self.limit));
+        headers.insert("RateLimit-Remaining", HeaderValue::from(self.remaining));
+        headers.insert(
+            "RateLimit-Reset",
+            HeaderValue::from(crate::error::ceil_secs(self.reset)),
+        );
+    }
+}
+
+/// Token buckets keyed by authenticated user and by client address. Each
+/// bucket holds up to `burst` requests and refills at `rate` per second.
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<Key, Bucket>>,
+    burst: u32,
+    rate: f64,
+    trusted_proxies: Vec<IpAddr>,
+}
+
+impl RateLimiter {
+    pub fn from_config(config: &crate::config::Config, class: RouteClass) -> Self {
+        let (burst, rate) = match class {
+            RouteClass::Auth => (config.rate_limit_auth_burst, config.rate_limit_auth_rate),
+            RouteClass::Api => (config.rate_limit_api_burst, config.rate_limit_api_rate),
+        };
+        RateLimiter {
+            buckets: Mutex::new(HashMap::new()),
+            burst,
+            rate,
+            trusted_proxies: config.trusted_proxies.clone(),
+        }
+    }
+
+    fn take(&self, key: Key, now: Instant) -> Quota {
+        let capacity = f64::from(self.burst);
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED && !buckets.contains_key(&key) {
+            // Buckets that have refilled completely carry no information.
+            buckets.retain(|_, bucket| {
+                bucket.tokens + (now - bucket.updated).as_secs_f64() * self.rate < capacity
+            });
+            // Still full: drop the bucket left alone the longest, which is
+            // the closest to full again.
+            if buckets.len() >= MAX_TRACKED {
+                let stalest = buckets
+                    .iter()
+                    .min_by_key(|(_, bucket)| bucket.updated)
+                    .map(|(key, _)| key.clone());
+                if let Some(stalest) = stalest {
+                    buckets.remove(&stalest);
+                }
+            }
+        }
+
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        bucket.tokens =
+            (bucket.tokens + (now - bucket.updated).as_secs_f64() * self.rate).min(capacity);
+        bucket.updated = now;
+
+        let retry_after = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        };
+        Quota {
+            limit: self.burst,
+            remaining: bucket.tokens as u32,
+            reset: Duration::from_secs_f64((capacity - bucket.tokens) / self.rate),
+            retry_after,
+        }
+    }
+}
+
+/// Resolves the client address. `X-Forwarded-For` is only believed when the
+/// peer is a trusted proxy, and is walked from the right so that clients
+/// cannot prepend addresses of their choosing.
+pub fn client_ip(trusted_proxies: &[IpAddr], peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+    let hops: Vec<&str> = headers
+        .get_all(X_FORWARDED_FOR)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+
+    let mut client = peer;
+    for hop in hops.into_iter().rev() {
+        if !trusted_proxies.contains(&client) {
+            break;
+        }
+        match hop.parse::<IpAddr>() {
+            Ok(ip) => client = ip,
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+/// Charges the request against the client address. Must run outside
+/// `authenticate`, so that credential checks are only reached within the
+/// address limit.
+pub async fn enforce_ip<B>(
+    State(limiter): State<Arc<RateLimiter>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let ip = client_ip(&limiter.trusted_proxies, peer.ip(), request.headers());
+    let quota = limiter.take(Key::Ip(ip), Instant::now());
+    charge(quota, "rate_limited_ip", request, next).await
+}
+
+/// Charges the request against the authenticated user, if any. Must run
+/// inside `authenticate`, which establishes the session.
+pub async fn enforce_user<B>(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let username = request
+        .extensions()
+        .get::<Session>()
+        .and_then(|session| session.username.clone());
+    match username {
+        Some(username) => {
+            let quota = limiter.take(Key::User(username), Instant::now());
+            charge(quota, "rate_limited_user", request, next).await
+        }
+        None => next.run(request).await,
+    }
+}
+
+async fn charge<B>(quota: Quota, reason: &str, request: Request<B>, next: Next<B>) -> Response {
+    if let Some(retry_after) = quota.retry_after {
+        crate::metrics::metrics().record_rejection(reason);
+        let mut response = crate::error::Error::TooManyRequests(retry_after).into_response();
+        quota.apply(response.headers_mut());
+        return response;
+    }
+
+    let mut response = next.run(request).await;
+    quota.apply(response.headers_mut());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    fn forwarded_for(hops: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for hop in hops {
+            headers.append(X_FORWARDED_FOR, HeaderValue::from_str(hop).unwrap());
+        }
+        headers
+    }
+
+    fn limiter(burst: u32, rate: f64) -> RateLimiter {
+        RateLimiter {
+            buckets: Mutex::new(HashMap::new()),
+            burst,
+            rate,
+            trusted_proxies: vec![],
+        }
+    }
+
+    #[test]
+    fn client_ip_ignores_forwarded_for_from_untrusted_peers() {
+        let headers = forwarded_for(&["203.0.113.7"]);
+        assert_eq!(
+            client_ip(&[], ip("198.51.100.1"), &headers),
+            ip("198.51.100.1")
+        );
+        assert_eq!(
+            client_ip(&[ip("10.0.0.1")], ip("198.51.100.1"), &headers),
+            ip("198.51.100.1")
+        );
+    }
+
+    #[test]
+    fn client_ip_walks_trusted_proxies_from_the_right() {
+        let proxies = [ip("10.0.0.1"), ip("10.0.0.2")];
+        // The client prepended a spoofed address, which is never reached.
+        let headers = forwarded_for(&["192.0.2.66, 203.0.113.7", "10.0.0.2"]);
+        assert_eq!(
+            client_ip(&proxies, ip("10.0.0.1"), &headers),
+            ip("203.0.113.7")
+        );
+        assert_eq!(
+            client_ip(&proxies, ip("10.0.0.1"), &HeaderMap::new()),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn client_ip_stops_at_a_garbage_hop() {
+        let proxies = [ip("10.0.0.1")];
+        let headers = forwarded_for(&["203.0.113.7, unknown"]);
+        assert_eq!(
+            client_ip(&proxies, ip("10.0.0.1"), &headers),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn take_spends_the_burst_and_reports_retry_after() {
+        let limiter = limiter(2, 0.5);
+        let key = Key::Ip(ip("203.0.113.7"));
+        let now = Instant::now();
+
+        let first = limiter.take(key.clone(), now);
+        assert_eq!((first.limit, first.remaining), (2, 1));
+        assert_eq!(first.retry_after, None);
+        assert_eq!(first.reset, Duration::from_secs(2));
+        assert_eq!(limiter.take(key.clone(), now).remaining, 0);
+
+        let refused = limiter.take(key.clone(), now);
+        assert_eq!(refused.retry_after, Some(Duration::from_secs(2)));
+        assert_eq!(refused.reset, Duration::from_secs(4));
+
+        // Other clients have buckets of their own.
+        let other = limiter.take(Key::User(String::from("alice")), now);
+        assert_eq!(other.retry_after, None);
+    }
+
+    #[test]
+    fn take_refills_at_the_rate_up_to_the_burst() {
+        let limiter = limiter(2, 0.5);
+        let key = Key::Ip(ip("203.0.113.7"));
+        let now = Instant::now();
+        limiter.take(key.clone(), now);
+        limiter.take(key.clone(), now);
+
+        let halfway = limiter.take(key.clone(), now + Duration::from_secs(1));
+        assert_eq!(halfway.retry_after, Some(Duration::from_secs(1)));
+
+        let refilled = limiter.take(key.clone(), now + Duration::from_secs(2));
+        assert_eq!(refilled.retry_after, None);
+
+        let much_later = limiter.take(key, now + Duration::from_secs(3600));
+        assert_eq!(much_later.remaining, 1);
+    }
+}
@@ -0,0 +1,57 @@
+use axum::{middleware::Next, response::Response};
+use hyper::{
+    header::{HeaderName, HeaderValue},
+    Request,
+};
+use tracing::Instrument;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest client-supplied id we propagate; anything else is replaced.
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// Correlation id of the current request, available as a request extension.
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+/// Accepts the caller's `X-Request-Id` or generates one, runs the request
+/// inside a span carrying it and echoes it back in the response.
+pub async fn propagate<B>(mut request: Request<B>, next: Next<B>) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| is_valid(value))
+        .map(|value| value.to_string())
+        .unwrap_or_else(generate);
+
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %request.method(),
+        path = %request.uri().path(),
+    );
+    request
+        .extensions_mut()
+        .insert(RequestId(request_id.clone()));
+
+    let mut response = next.run(request).instrument(span).await;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// Only ids that are safe to log and echo are accepted from clients.
+fn is_valid(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= MAX_REQUEST_ID_LENGTH
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
+
+fn generate() -> String {
+    let bytes: [u8; 16] = rand::random();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,36 @@
+-- Add migration script here
+CREATE TABLE permissions(
+    space_id BIGINT NOT NULL REFERENCES spaces(space_id),
+    user_id VARCHAR(30) NOT NULL REFERENCES users(user_id),
+    perms VARCHAR(3) NOT NULL,
+    PRIMARY KEY (space_id, user_id)
+);
+INSERT INTO permissions(space_id, user_id, perms)
+    SELECT space_id, owner, 'rwd' FROM spaces WHERE owner IN (SELECT user_id FROM users);
+GRANT SELECT, INSERT, UPDATE ON permissions TO natter_api_user;
+
+CREATE TABLE groups(
+    group_id BIGINT PRIMARY KEY,
+    name VARCHAR(30) NOT NULL,
+    owner VARCHAR(30) NOT NULL REFERENCES users(user_id)
+);
+CREATE SEQUENCE group_id_seq OWNED BY groups.group_id;
+CREATE UNIQUE INDEX group_name_idx ON groups(name);
+GRANT SELECT, INSERT ON groups TO natter_api_user;
+GRANT SELECT, USAGE ON group_id_seq TO natter_api_user;
+
+CREATE TABLE group_members(
+    group_id BIGINT NOT NULL REFERENCES groups(group_id),
+    user_id VARCHAR(30) NOT NULL REFERENCES users(user_id),
+    PRIMARY KEY (group_id, user_id)
+);
+CREATE INDEX group_member_user_idx ON group_members(user_id);
+GRANT SELECT, INSERT, DELETE ON group_members TO natter_api_user;
+
+CREATE TABLE group_permissions(
+    space_id BIGINT NOT NULL REFERENCES spaces(space_id),
+    group_id BIGINT NOT NULL REFERENCES groups(group_id),
+    perms VARCHAR(3) NOT NULL,
+    PRIMARY KEY (space_id, group_id)
+);
+GRANT SELECT, INSERT, UPDATE ON group_permissions TO natter_api_user;
@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE password_reset_tokens(
+    token_hash VARCHAR(64) PRIMARY KEY,
+    user_id VARCHAR(30) NOT NULL REFERENCES users(user_id),
+    expiry TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX password_reset_user_idx ON password_reset_tokens(user_id);
+GRANT SELECT, INSERT, DELETE ON password_reset_tokens TO natter_api_user;
+GRANT UPDATE (pw_hash) ON users TO natter_api_user;
@@ -0,0 +1,15 @@
+-- Add migration script here
+CREATE TABLE mfa_credentials(
+    user_id VARCHAR(30) PRIMARY KEY REFERENCES users(user_id),
+    secret VARCHAR(255) NOT NULL,
+    confirmed BOOLEAN NOT NULL DEFAULT FALSE,
+    last_used_step BIGINT NOT NULL DEFAULT 0
+);
+GRANT SELECT, INSERT, UPDATE ON mfa_credentials TO natter_api_user;
+
+CREATE TABLE mfa_recovery_codes(
+    user_id VARCHAR(30) NOT NULL REFERENCES users(user_id),
+    code_hash VARCHAR(64) NOT NULL,
+    PRIMARY KEY (user_id, code_hash)
+);
+GRANT SELECT, INSERT, DELETE ON mfa_recovery_codes TO natter_api_user;
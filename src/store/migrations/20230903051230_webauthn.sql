@@ -0,0 +1,27 @@
+-- Add migration script here
+CREATE TABLE webauthn_credentials(
+    credential_id VARCHAR(1366) PRIMARY KEY,
+    user_id VARCHAR(30) NOT NULL REFERENCES users(user_id),
+    public_key BYTEA NOT NULL,
+    sign_count BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX webauthn_credential_user_idx ON webauthn_credentials(user_id);
+GRANT SELECT, INSERT ON webauthn_credentials TO natter_api_user;
+GRANT UPDATE (sign_count) ON webauthn_credentials TO natter_api_user;
+
+CREATE TABLE webauthn_challenges(
+    challenge VARCHAR(64) PRIMARY KEY,
+    user_id VARCHAR(30) NULL REFERENCES users(user_id),
+    ceremony VARCHAR(10) NOT NULL,
+    expiry TIMESTAMPTZ NOT NULL
+);
+GRANT SELECT, INSERT, DELETE ON webauthn_challenges TO natter_api_user;
+
+CREATE TABLE tokens(
+    token_hash VARCHAR(64) PRIMARY KEY,
+    user_id VARCHAR(30) NOT NULL REFERENCES users(user_id),
+    expiry TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX token_user_idx ON tokens(user_id);
+GRANT SELECT, INSERT, DELETE ON tokens TO natter_api_user;
@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE audit_log ADD COLUMN event VARCHAR(30) NULL;
+ALTER TABLE audit_log ADD COLUMN client_ip VARCHAR(45) NULL;
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE audit_log ADD COLUMN request_id VARCHAR(128) NULL;
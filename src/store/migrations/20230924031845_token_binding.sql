@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE tokens ADD COLUMN x5t_s256 VARCHAR(43) NULL;
@@ -0,0 +1,4 @@
+-- Add migration script here
+CREATE INDEX webauthn_challenge_expiry_idx ON webauthn_challenges(expiry);
+CREATE INDEX token_expiry_idx ON tokens(expiry);
+CREATE INDEX password_reset_expiry_idx ON password_reset_tokens(expiry);
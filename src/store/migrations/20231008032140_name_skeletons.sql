@@ -0,0 +1,7 @@
+-- Add migration script here
+ALTER TABLE users ADD COLUMN name_skeleton TEXT;
+ALTER TABLE groups ADD COLUMN name_skeleton TEXT;
+ALTER TABLE spaces ADD COLUMN name_skeleton TEXT;
+CREATE UNIQUE INDEX user_name_skeleton_idx ON users(name_skeleton);
+CREATE UNIQUE INDEX group_name_skeleton_idx ON groups(name_skeleton);
+CREATE UNIQUE INDEX space_name_skeleton_idx ON spaces(name_skeleton);
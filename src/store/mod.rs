@@ -1,9 +1,23 @@
+use chrono::{DateTime, Utc};
 use sqlx::postgres::{PgPool, PgPoolOptions, PgRow};
 use sqlx::Row;
+use std::sync::Arc;
+use std::time::Duration;
 
+use crate::model::audit::AuditEvent;
+use crate::model::group::{Group, GroupId, NewGroup};
 use crate::model::message::{Message, MessageId};
-use crate::model::space::{Space, SpaceId};
-use crate::model::user::User;
+use crate::model::space::{NewSpace, Space, SpaceId};
+use crate::model::user::{BearerToken, MfaCredential, User};
+use crate::model::webauthn::WebauthnCredential;
+use crate::validation::{skeleton, Permissions};
+
+/// Sizing of a connection pool.
+pub struct PoolLimits {
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub acquire_timeout: Duration,
+}
 
 #[derive(Debug, Clone)]
 pub struct Store {
@@ -11,80 +25,323 @@ pub struct Store {
 }
 
 impl Store {
-    pub async fn new_from_config(config: &crate::config::Config) -> Self {
+    pub async fn new_from_config(
+        config: &crate::config::Config,
+    ) -> Result<Self, crate::error::Error> {
         Store::new_from_url(
             &format!(
                 "postgres://{}:{}@{}:{}/{}",
-                config.db_user, config.db_password, config.db_host, config.db_port, config.db_name
+                config.db_user,
+                config.db_password.expose(),
+                config.db_host,
+                config.db_port,
+                config.db_name
             ),
             &format!(
                 "postgres://{}:{}@{}:{}/{}",
                 config.db_api_user,
-                config.db_api_password,
+                config.db_api_password.expose(),
                 config.db_host,
                 config.db_port,
                 config.db_name
             ),
+            PoolLimits {
+                max_connections: config.db_max_connections,
+                min_connections: config.db_min_connections,
+                acquire_timeout: Duration::from_secs(config.db_acquire_timeout),
+            },
+            config.db_connect_attempts,
+            Duration::from_secs(config.db_connect_max_backoff),
         )
         .await
     }
 
-    async fn new_from_url(db_url: &str, db_api_url: &str) -> Self {
-        let db_pool = match PgPoolOptions::new()
-            .max_connections(1)
-            .connect(db_url)
-            .await
-        {
-            Ok(pool) => {
-                tracing::event!(
-                    tracing::Level::INFO,
-                    "store::connected success for migration"
-                );
-                pool
-            }
-            Err(e) => panic!(
-                "store::new Unable to connect to the Db for migration: {:?}",
-                e
-            ),
+    async fn new_from_url(
+        db_url: &str,
+        db_api_url: &str,
+        api_pool: PoolLimits,
+        connect_attempts: u32,
+        max_backoff: Duration,
+    ) -> Result<Self, crate::error::Error> {
+        let migration_pool = PoolLimits {
+            max_connections: 1,
+            min_connections: 0,
+            acquire_timeout: api_pool.acquire_timeout,
         };
+        let db_pool =
+            connect_with_retry(&migration_pool, db_url, connect_attempts, max_backoff).await?;
+        tracing::event!(
+            tracing::Level::INFO,
+            "store::connected success for migration"
+        );
 
-        match sqlx::migrate!("./src/store/migrations/")
+        let migration = sqlx::migrate!("./src/store/migrations/")
             .run(&db_pool)
-            .await
-        {
+            .await;
+        match migration {
             Ok(res) => tracing::event!(tracing::Level::INFO, "store::migrated success {:?}", res),
-            Err(e) => tracing::event!(tracing::Level::ERROR, "store::migrated error {:?}", e),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::migrated error {:?}", e);
+                db_pool.close().await;
+                return Err(crate::error::Error::MigrationError(e));
+            }
         };
+        let backfill = backfill_skeletons(&db_pool).await;
         db_pool.close().await;
+        backfill?;
+
+        let db_api_pool =
+            connect_with_retry(&api_pool, db_api_url, connect_attempts, max_backoff).await?;
+        tracing::event!(tracing::Level::INFO, "store::connected success for api");
+
+        Ok(Store {
+            connection: db_api_pool,
+        })
+    }
+
+    /// Waits for in-flight queries and closes every pooled connection.
+    pub async fn close(&self) {
+        self.connection.close().await;
+        tracing::event!(tracing::Level::INFO, "store::closed");
+    }
+
+    /// Periodically deletes expired tokens and challenges, which are never
+    /// redeemed and would otherwise accumulate.
+    pub async fn purge_expired(self: Arc<Self>, interval: Duration) {
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Ok(deleted) = self.delete_expired().await {
+                tracing::event!(
+                    tracing::Level::DEBUG,
+                    "store::purge_expired deleted {} rows",
+                    deleted
+                );
+            }
+        }
+    }
+
+    async fn delete_expired(&self) -> Result<u64, crate::error::Error> {
+        let mut deleted = 0;
+        for query in [
+            "DELETE FROM webauthn_challenges WHERE expiry <= now();",
+            "DELETE FROM tokens WHERE expiry <= now();",
+            "DELETE FROM password_reset_tokens WHERE expiry <= now();",
+        ] {
+            match sqlx::query(query).execute(&self.connection).await {
+                Ok(result) => deleted += result.rows_affected(),
+                Err(e) => {
+                    tracing::event!(tracing::Level::ERROR, "store::delete_expired {:?}", e);
+                    return Err(crate::error::Error::DatabaseQueryError(e));
+                }
+            }
+        }
+        Ok(deleted)
+    }
 
-        let db_api_pool = match PgPoolOptions::new()
-            .max_connections(5)
-            .connect(db_api_url)
+    pub async fn ping(&self) -> Result<(), crate::error::Error> {
+        match sqlx::query("SELECT 1").execute(&self.connection).await {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::ping {:?}", e);
+                Err(crate::error::Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    pub async fn create_space(&self, new_space: NewSpace) -> Result<Space, crate::error::Error> {
+        let mut tx = self.connection.begin().await.map_err(|e| {
+            tracing::event!(tracing::Level::ERROR, "store::create_space {:?}", e);
+            crate::error::Error::DatabaseQueryError(e)
+        })?;
+
+        let name_skeleton = skeleton(&new_space.name);
+        let space = match sqlx::query("INSERT INTO spaces (space_id, name, owner, name_skeleton) VALUES (nextval('space_id_seq'), $1, $2, $3) RETURNING space_id, name, owner;")
+            .bind(String::from(new_space.name))
+            .bind(String::from(new_space.owner))
+            .bind(name_skeleton)
+            .map(map_to_space)
+            .fetch_one(&mut tx)
             .await
         {
-            Ok(pool) => {
-                tracing::event!(tracing::Level::INFO, "store::connected success for api");
-                pool
+            Ok(space) => space,
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::create_space {:?}", e);
+                return Err(map_unique_violation(
+                    e,
+                    "Space name already taken or confusable with an existing one",
+                ));
             }
-            Err(e) => panic!("store::new Unable to connect to the Db: {:?}", e),
         };
 
-        Store {
-            connection: db_api_pool,
+        if let Err(e) = sqlx::query(
+            "INSERT INTO permissions (space_id, user_id, perms) VALUES ($1, $2, 'rwd');",
+        )
+        .bind(space.space_id.as_ref().map(|id| id.0))
+        .bind(&space.owner)
+        .execute(&mut tx)
+        .await
+        {
+            tracing::event!(tracing::Level::ERROR, "store::create_space {:?}", e);
+            return Err(crate::error::Error::DatabaseQueryError(e));
+        }
+
+        match tx.commit().await {
+            Ok(_) => Ok(space),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::create_space {:?}", e);
+                Err(crate::error::Error::DatabaseQueryError(e))
+            }
         }
     }
 
-    pub async fn create_space(&self, new_space: Space) -> Result<Space, crate::error::Error> {
-        match sqlx::query("INSERT INTO spaces (space_id, name, owner) VALUES (nextval('space_id_seq'), $1, $2) RETURNING space_id, name, owner;")
-            .bind(new_space.name)
-            .bind(new_space.owner)
-            .map(map_to_space)
+    /// Returns the union of the permissions granted on the space to the user
+    /// directly and through any group the user is a member of.
+    pub async fn get_permissions(
+        &self,
+        space_id: &SpaceId,
+        user_id: &str,
+    ) -> Result<Permissions, crate::error::Error> {
+        match sqlx::query(
+            "SELECT perms FROM permissions WHERE space_id = $1 AND user_id = $2
+            UNION ALL
+            SELECT gp.perms FROM group_permissions gp
+                JOIN group_members gm ON gm.group_id = gp.group_id
+                WHERE gp.space_id = $1 AND gm.user_id = $2;",
+        )
+        .bind(space_id.0)
+        .bind(user_id)
+        .map(|row: PgRow| row.get::<String, _>("perms"))
+        .fetch_all(&self.connection)
+        .await
+        {
+            Ok(grants) => Ok(Permissions::union(grants.iter().map(String::as_str))),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::get_permissions {:?}", e);
+                Err(crate::error::Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    pub async fn add_user_permissions(
+        &self,
+        space_id: &SpaceId,
+        user_id: &str,
+        perms: &str,
+    ) -> Result<(), crate::error::Error> {
+        match sqlx::query(
+            "INSERT INTO permissions (space_id, user_id, perms) VALUES ($1, $2, $3) ON CONFLICT (space_id, user_id) DO UPDATE SET perms = EXCLUDED.perms;",
+        )
+        .bind(space_id.0)
+        .bind(user_id)
+        .bind(perms)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::add_user_permissions {:?}", e);
+                Err(map_foreign_key_violation(e, "Unknown user"))
+            }
+        }
+    }
+
+    pub async fn add_group_permissions(
+        &self,
+        space_id: &SpaceId,
+        group_id: &GroupId,
+        perms: &str,
+    ) -> Result<(), crate::error::Error> {
+        match sqlx::query(
+            "INSERT INTO group_permissions (space_id, group_id, perms) VALUES ($1, $2, $3) ON CONFLICT (space_id, group_id) DO UPDATE SET perms = EXCLUDED.perms;",
+        )
+        .bind(space_id.0)
+        .bind(group_id.0)
+        .bind(perms)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::add_group_permissions {:?}", e);
+                Err(crate::error::Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    pub async fn create_group(&self, new_group: NewGroup) -> Result<Group, crate::error::Error> {
+        let name_skeleton = skeleton(&new_group.name);
+        match sqlx::query("INSERT INTO groups (group_id, name, owner, name_skeleton) VALUES (nextval('group_id_seq'), $1, $2, $3) RETURNING group_id, name, owner;")
+            .bind(String::from(new_group.name))
+            .bind(String::from(new_group.owner))
+            .bind(name_skeleton)
+            .map(map_to_group)
             .fetch_one(&self.connection)
             .await
         {
-            Ok(space) => Ok(space),
+            Ok(group) => Ok(group),
             Err(e) => {
-                tracing::event!(tracing::Level::ERROR, "store::create_space {:?}", e);
+                tracing::event!(tracing::Level::ERROR, "store::create_group {:?}", e);
+                Err(map_unique_violation(
+                    e,
+                    "Group name already taken or confusable with an existing one",
+                ))
+            }
+        }
+    }
+
+    pub async fn get_group_by_id(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Option<Group>, crate::error::Error> {
+        match sqlx::query("SELECT group_id, name, owner FROM groups WHERE group_id = $1;")
+            .bind(group_id.0)
+            .map(map_to_group)
+            .fetch_optional(&self.connection)
+            .await
+        {
+            Ok(group) => Ok(group),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::get_group_by_id {:?}", e);
+                Err(crate::error::Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    pub async fn add_group_member(
+        &self,
+        group_id: &GroupId,
+        user_id: &str,
+    ) -> Result<(), crate::error::Error> {
+        match sqlx::query(
+            "INSERT INTO group_members (group_id, user_id) VALUES ($1, $2) ON CONFLICT DO NOTHING;",
+        )
+        .bind(group_id.0)
+        .bind(user_id)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::add_group_member {:?}", e);
+                Err(map_foreign_key_violation(e, "Unknown user"))
+            }
+        }
+    }
+
+    pub async fn remove_group_member(
+        &self,
+        group_id: &GroupId,
+        user_id: &str,
+    ) -> Result<bool, crate::error::Error> {
+        match sqlx::query("DELETE FROM group_members WHERE group_id = $1 AND user_id = $2;")
+            .bind(group_id.0)
+            .bind(user_id)
+            .execute(&self.connection)
+            .await
+        {
+            Ok(result) => Ok(result.rows_affected() > 0),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::remove_group_member {:?}", e);
                 Err(crate::error::Error::DatabaseQueryError(e))
             }
         }
@@ -113,11 +370,13 @@ impl Store {
     }
 
     pub async fn create_user(&self, new_user: User) -> Result<User, crate::error::Error> {
+        let name_skeleton = skeleton(&new_user.user_id);
         match sqlx::query(
-            "INSERT INTO users(user_id, pw_hash) VALUES ($1, $2) RETURNING user_id, pw_hash;",
+            "INSERT INTO users(user_id, pw_hash, name_skeleton) VALUES ($1, $2, $3) RETURNING user_id, pw_hash;",
         )
         .bind(new_user.user_id)
         .bind(new_user.pw_hash)
+        .bind(name_skeleton)
         .map(map_to_user)
         .fetch_one(&self.connection)
         .await
@@ -125,26 +384,572 @@ impl Store {
             Ok(user) => Ok(user),
             Err(e) => {
                 tracing::event!(tracing::Level::ERROR, "store::create_user {:?}", e);
-                Err(crate::error::Error::DatabaseQueryError(e))
+                Err(map_unique_violation(
+                    e,
+                    "Username already taken or confusable with an existing one",
+                ))
             }
         }
     }
-    pub async fn get_user_by_id(&self, user_id: &str) -> Result<User, crate::error::Error> {
+    pub async fn get_user_by_id(&self, user_id: &str) -> Result<Option<User>, crate::error::Error> {
         match sqlx::query("SELECT user_id, pw_hash FROM users WHERE user_id = $1;")
             .bind(user_id)
             .map(map_to_user)
-            .fetch_one(&self.connection)
+            .fetch_optional(&self.connection)
             .await
         {
             Ok(user) => Ok(user),
             Err(e) => {
-                tracing::event!(tracing::Level::ERROR, "store::create_user {:?}", e);
+                tracing::event!(tracing::Level::ERROR, "store::get_user_by_id {:?}", e);
+                Err(crate::error::Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    pub async fn update_password(
+        &self,
+        user_id: &str,
+        pw_hash: &str,
+    ) -> Result<(), crate::error::Error> {
+        match sqlx::query("UPDATE users SET pw_hash = $2 WHERE user_id = $1;")
+            .bind(user_id)
+            .bind(pw_hash)
+            .execute(&self.connection)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::update_password {:?}", e);
+                Err(crate::error::Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    pub async fn create_reset_token(
+        &self,
+        token_hash: &str,
+        user_id: &str,
+        expiry: DateTime<Utc>,
+    ) -> Result<(), crate::error::Error> {
+        match sqlx::query(
+            "INSERT INTO password_reset_tokens (token_hash, user_id, expiry) VALUES ($1, $2, $3);",
+        )
+        .bind(token_hash)
+        .bind(user_id)
+        .bind(expiry)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::create_reset_token {:?}", e);
+                Err(crate::error::Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Deletes the reset token and reports whether it was valid, so that a
+    /// token can never be redeemed twice.
+    pub async fn consume_reset_token(
+        &self,
+        token_hash: &str,
+        user_id: &str,
+    ) -> Result<bool, crate::error::Error> {
+        match sqlx::query(
+            "DELETE FROM password_reset_tokens WHERE token_hash = $1 AND user_id = $2 AND expiry > now();",
+        )
+        .bind(token_hash)
+        .bind(user_id)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(result) => Ok(result.rows_affected() > 0),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::consume_reset_token {:?}", e);
+                Err(crate::error::Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    pub async fn delete_reset_tokens(&self, user_id: &str) -> Result<(), crate::error::Error> {
+        match sqlx::query("DELETE FROM password_reset_tokens WHERE user_id = $1;")
+            .bind(user_id)
+            .execute(&self.connection)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::delete_reset_tokens {:?}", e);
+                Err(crate::error::Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    pub async fn get_mfa_credential(
+        &self,
+        user_id: &str,
+    ) -> Result<Option<MfaCredential>, crate::error::Error> {
+        match sqlx::query(
+            "SELECT user_id, secret, confirmed, last_used_step FROM mfa_credentials WHERE user_id = $1;",
+        )
+        .bind(user_id)
+        .map(map_to_mfa_credential)
+        .fetch_optional(&self.connection)
+        .await
+        {
+            Ok(credential) => Ok(credential),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::get_mfa_credential {:?}", e);
+                Err(crate::error::Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Stores a pending TOTP secret. Returns `false` if MFA is already
+    /// confirmed for the user, in which case nothing is changed.
+    pub async fn save_mfa_enrollment(
+        &self,
+        user_id: &str,
+        secret: &str,
+    ) -> Result<bool, crate::error::Error> {
+        match sqlx::query(
+            "INSERT INTO mfa_credentials (user_id, secret, confirmed, last_used_step) VALUES ($1, $2, FALSE, 0)
+                ON CONFLICT (user_id) DO UPDATE SET secret = EXCLUDED.secret, last_used_step = 0
+                WHERE mfa_credentials.confirmed = FALSE;",
+        )
+        .bind(user_id)
+        .bind(secret)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(result) => Ok(result.rows_affected() > 0),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::save_mfa_enrollment {:?}", e);
+                Err(crate::error::Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    pub async fn confirm_mfa(
+        &self,
+        user_id: &str,
+        step: i64,
+        recovery_code_hashes: &[String],
+    ) -> Result<(), crate::error::Error> {
+        let log_error = |e: sqlx::Error| {
+            tracing::event!(tracing::Level::ERROR, "store::confirm_mfa {:?}", e);
+            crate::error::Error::DatabaseQueryError(e)
+        };
+        let mut tx = self.connection.begin().await.map_err(log_error)?;
+
+        sqlx::query(
+            "UPDATE mfa_credentials SET confirmed = TRUE, last_used_step = $2 WHERE user_id = $1;",
+        )
+        .bind(user_id)
+        .bind(step)
+        .execute(&mut tx)
+        .await
+        .map_err(log_error)?;
+
+        sqlx::query("DELETE FROM mfa_recovery_codes WHERE user_id = $1;")
+            .bind(user_id)
+            .execute(&mut tx)
+            .await
+            .map_err(log_error)?;
+
+        for code_hash in recovery_code_hashes {
+            sqlx::query("INSERT INTO mfa_recovery_codes (user_id, code_hash) VALUES ($1, $2);")
+                .bind(user_id)
+                .bind(code_hash)
+                .execute(&mut tx)
+                .await
+                .map_err(log_error)?;
+        }
+
+        tx.commit().await.map_err(log_error)
+    }
+
+    /// Records the TOTP time step as used. Returns `false` if the step, or a
+    /// later one, was already used, which means the code is being replayed.
+    pub async fn use_totp_step(
+        &self,
+        user_id: &str,
+        step: i64,
+    ) -> Result<bool, crate::error::Error> {
+        match sqlx::query(
+            "UPDATE mfa_credentials SET last_used_step = $2 WHERE user_id = $1 AND last_used_step < $2;",
+        )
+        .bind(user_id)
+        .bind(step)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(result) => Ok(result.rows_affected() > 0),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::use_totp_step {:?}", e);
+                Err(crate::error::Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    pub async fn use_recovery_code(
+        &self,
+        user_id: &str,
+        code_hash: &str,
+    ) -> Result<bool, crate::error::Error> {
+        match sqlx::query("DELETE FROM mfa_recovery_codes WHERE user_id = $1 AND code_hash = $2;")
+            .bind(user_id)
+            .bind(code_hash)
+            .execute(&self.connection)
+            .await
+        {
+            Ok(result) => Ok(result.rows_affected() > 0),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::use_recovery_code {:?}", e);
+                Err(crate::error::Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    pub async fn create_webauthn_challenge(
+        &self,
+        challenge: &str,
+        user_id: Option<&str>,
+        ceremony: &str,
+        expiry: DateTime<Utc>,
+    ) -> Result<(), crate::error::Error> {
+        match sqlx::query(
+            "INSERT INTO webauthn_challenges (challenge, user_id, ceremony, expiry) VALUES ($1, $2, $3, $4);",
+        )
+        .bind(challenge)
+        .bind(user_id)
+        .bind(ceremony)
+        .bind(expiry)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::create_webauthn_challenge {:?}", e);
+                Err(crate::error::Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Deletes the challenge so it can only be answered once. Returns `None`
+    /// if the challenge is unknown or expired, otherwise the user it was
+    /// issued to, if any.
+    pub async fn consume_webauthn_challenge(
+        &self,
+        challenge: &str,
+        ceremony: &str,
+    ) -> Result<Option<Option<String>>, crate::error::Error> {
+        match sqlx::query(
+            "DELETE FROM webauthn_challenges WHERE challenge = $1 AND ceremony = $2 AND expiry > now() RETURNING user_id;",
+        )
+        .bind(challenge)
+        .bind(ceremony)
+        .map(|row: PgRow| row.get("user_id"))
+        .fetch_optional(&self.connection)
+        .await
+        {
+            Ok(user_id) => Ok(user_id),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::consume_webauthn_challenge {:?}", e);
+                Err(crate::error::Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    pub async fn create_webauthn_credential(
+        &self,
+        credential: WebauthnCredential,
+    ) -> Result<WebauthnCredential, crate::error::Error> {
+        match sqlx::query(
+            "INSERT INTO webauthn_credentials (credential_id, user_id, public_key, sign_count) VALUES ($1, $2, $3, $4) RETURNING credential_id, user_id, public_key, sign_count;",
+        )
+        .bind(credential.credential_id)
+        .bind(credential.user_id)
+        .bind(credential.public_key)
+        .bind(credential.sign_count)
+        .map(map_to_webauthn_credential)
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(credential) => Ok(credential),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::create_webauthn_credential {:?}", e);
+                Err(map_unique_violation(e, "Credential already registered"))
+            }
+        }
+    }
+
+    pub async fn get_webauthn_credential(
+        &self,
+        credential_id: &str,
+    ) -> Result<Option<WebauthnCredential>, crate::error::Error> {
+        match sqlx::query(
+            "SELECT credential_id, user_id, public_key, sign_count FROM webauthn_credentials WHERE credential_id = $1;",
+        )
+        .bind(credential_id)
+        .map(map_to_webauthn_credential)
+        .fetch_optional(&self.connection)
+        .await
+        {
+            Ok(credential) => Ok(credential),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::get_webauthn_credential {:?}", e);
+                Err(crate::error::Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    pub async fn get_webauthn_credential_ids(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<String>, crate::error::Error> {
+        match sqlx::query("SELECT credential_id FROM webauthn_credentials WHERE user_id = $1;")
+            .bind(user_id)
+            .map(|row: PgRow| row.get("credential_id"))
+            .fetch_all(&self.connection)
+            .await
+        {
+            Ok(credential_ids) => Ok(credential_ids),
+            Err(e) => {
+                tracing::event!(
+                    tracing::Level::ERROR,
+                    "store::get_webauthn_credential_ids {:?}",
+                    e
+                );
+                Err(crate::error::Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    /// Stores the new signature counter. Returns `false` if the counter did
+    /// not increase, which indicates a cloned authenticator.
+    pub async fn update_webauthn_sign_count(
+        &self,
+        credential_id: &str,
+        sign_count: i64,
+    ) -> Result<bool, crate::error::Error> {
+        match sqlx::query(
+            "UPDATE webauthn_credentials SET sign_count = $2 WHERE credential_id = $1 AND ((sign_count = 0 AND $2 = 0) OR sign_count < $2);",
+        )
+        .bind(credential_id)
+        .bind(sign_count)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(result) => Ok(result.rows_affected() > 0),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::update_webauthn_sign_count {:?}", e);
+                Err(crate::error::Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    pub async fn create_token(
+        &self,
+        token_hash: &str,
+        user_id: &str,
+        expiry: DateTime<Utc>,
+        x5t_s256: Option<&str>,
+    ) -> Result<(), crate::error::Error> {
+        match sqlx::query(
+            "INSERT INTO tokens (token_hash, user_id, expiry, x5t_s256) VALUES ($1, $2, $3, $4);",
+        )
+        .bind(token_hash)
+        .bind(user_id)
+        .bind(expiry)
+        .bind(x5t_s256)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::create_token {:?}", e);
+                Err(crate::error::Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    pub async fn get_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<BearerToken>, crate::error::Error> {
+        match sqlx::query(
+            "SELECT user_id, x5t_s256 FROM tokens WHERE token_hash = $1 AND expiry > now();",
+        )
+        .bind(token_hash)
+        .map(|row: PgRow| BearerToken {
+            user_id: row.get("user_id"),
+            x5t_s256: row.get("x5t_s256"),
+        })
+        .fetch_optional(&self.connection)
+        .await
+        {
+            Ok(token) => Ok(token),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::get_token {:?}", e);
+                Err(crate::error::Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    pub async fn delete_tokens(&self, user_id: &str) -> Result<(), crate::error::Error> {
+        match sqlx::query("DELETE FROM tokens WHERE user_id = $1;")
+            .bind(user_id)
+            .execute(&self.connection)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::delete_tokens {:?}", e);
+                Err(crate::error::Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    pub async fn create_audit_event(&self, event: AuditEvent) -> Result<(), crate::error::Error> {
+        match sqlx::query(
+            "INSERT INTO audit_log (audit_id, method, path, user_id, status, event, client_ip, request_id, audit_time) VALUES (nextval('audit_id_seq'), $1, $2, $3, $4, $5, $6, $7, now());",
+        )
+        .bind(event.method)
+        .bind(event.path)
+        .bind(event.user_id)
+        .bind(event.status)
+        .bind(event.event)
+        .bind(event.client_ip)
+        .bind(event.request_id)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "store::create_audit_event {:?}", e);
                 Err(crate::error::Error::DatabaseQueryError(e))
             }
         }
     }
 }
 
+/// Connects with exponential backoff so the API can start before the
+/// database is reachable, e.g. while both containers are coming up.
+async fn connect_with_retry(
+    limits: &PoolLimits,
+    url: &str,
+    attempts: u32,
+    max_backoff: Duration,
+) -> Result<PgPool, crate::error::Error> {
+    let mut backoff = Duration::from_millis(500);
+    let mut attempt = 1;
+    loop {
+        match PgPoolOptions::new()
+            .max_connections(limits.max_connections)
+            .min_connections(limits.min_connections)
+            .acquire_timeout(limits.acquire_timeout)
+            .connect(url)
+            .await
+        {
+            Ok(pool) => return Ok(pool),
+            Err(e) if attempt < attempts => {
+                tracing::event!(
+                    tracing::Level::WARN,
+                    "store::connect attempt {}/{} failed, retrying in {:?}: {:?}",
+                    attempt,
+                    attempts,
+                    backoff,
+                    e
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(max_backoff);
+                attempt += 1;
+            }
+            Err(e) => {
+                tracing::event!(
+                    tracing::Level::ERROR,
+                    "store::connect giving up after {} attempts: {:?}",
+                    attempt,
+                    e
+                );
+                return Err(crate::error::Error::DatabaseQueryError(e));
+            }
+        }
+    }
+}
+
+/// Fills in the skeletons of names created before confusable detection.
+/// A name confusable with one processed earlier keeps a NULL skeleton and is
+/// logged, since renaming it is up to an operator.
+async fn backfill_skeletons(pool: &PgPool) -> Result<(), crate::error::Error> {
+    for (table, name_column, id_column) in [
+        ("users", "user_id", "user_id"),
+        ("groups", "name", "group_id"),
+        ("spaces", "name", "space_id"),
+    ] {
+        let names: Vec<String> = sqlx::query_scalar(&format!(
+            "SELECT {} FROM {} WHERE name_skeleton IS NULL ORDER BY {};",
+            name_column, table, id_column
+        ))
+        .fetch_all(pool)
+        .await
+        .map_err(|e| {
+            tracing::event!(tracing::Level::ERROR, "store::backfill_skeletons {:?}", e);
+            crate::error::Error::DatabaseQueryError(e)
+        })?;
+
+        let update = format!(
+            "UPDATE {} SET name_skeleton = $1 WHERE {} = $2;",
+            table, name_column
+        );
+        for name in names {
+            match sqlx::query(&update)
+                .bind(skeleton(&name))
+                .bind(&name)
+                .execute(pool)
+                .await
+            {
+                Ok(_) => {}
+                Err(e) => match map_unique_violation(e, "") {
+                    crate::error::Error::Conflict(_) => tracing::event!(
+                        tracing::Level::WARN,
+                        "store::backfill_skeletons {} {} is confusable with an existing name",
+                        table,
+                        name
+                    ),
+                    e => {
+                        tracing::event!(tracing::Level::ERROR, "store::backfill_skeletons {:?}", e);
+                        return Err(e);
+                    }
+                },
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reports unique constraint violations as conflicts rather than server errors.
+fn map_unique_violation(e: sqlx::Error, message: &str) -> crate::error::Error {
+    match &e {
+        sqlx::Error::Database(db_error) if db_error.code().as_deref() == Some("23505") => {
+            crate::error::Error::Conflict(String::from(message))
+        }
+        _ => crate::error::Error::DatabaseQueryError(e),
+    }
+}
+
+/// Referencing a row that does not exist, such as granting permissions to an
+/// unknown user, is a client error rather than a failed query.
+fn map_foreign_key_violation(e: sqlx::Error, message: &str) -> crate::error::Error {
+    match &e {
+        sqlx::Error::Database(db_error) if db_error.code().as_deref() == Some("23503") => {
+            crate::error::Error::IllegalArgumentException(String::from(message))
+        }
+        _ => crate::error::Error::DatabaseQueryError(e),
+    }
+}
+
 fn map_to_space(row: PgRow) -> Space {
     Space {
         space_id: Some(SpaceId(row.get("space_id"))),
@@ -153,6 +958,14 @@ fn map_to_space(row: PgRow) -> Space {
     }
 }
 
+fn map_to_group(row: PgRow) -> Group {
+    Group {
+        group_id: Some(GroupId(row.get("group_id"))),
+        name: row.get("name"),
+        owner: row.get("owner"),
+    }
+}
+
 fn map_to_message(row: PgRow) -> Message {
     Message {
         space_id: SpaceId(row.get("space_id")),
@@ -169,3 +982,21 @@ fn map_to_user(row: PgRow) -> User {
         pw_hash: row.get("pw_hash"),
     }
 }
+
+fn map_to_mfa_credential(row: PgRow) -> MfaCredential {
+    MfaCredential {
+        user_id: row.get("user_id"),
+        secret: row.get("secret"),
+        confirmed: row.get("confirmed"),
+        last_used_step: row.get("last_used_step"),
+    }
+}
+
+fn map_to_webauthn_credential(row: PgRow) -> WebauthnCredential {
+    WebauthnCredential {
+        credential_id: row.get("credential_id"),
+        user_id: row.get("user_id"),
+        public_key: row.get("public_key"),
+        sign_count: row.get("sign_count"),
+    }
+}
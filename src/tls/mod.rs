@@ -0,0 +1,293 @@
+use std::fs;
+use std::io::BufReader;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+
+use axum::{
+    extract::State,
+    response::{IntoResponse, Redirect, Response},
+};
+use base64::{engine::general_purpose, Engine as _};
+use hyper::header::{self, HeaderMap};
+use hyper::http::uri::Authority;
+use hyper::server::accept::Accept;
+use hyper::Uri;
+use rustls::server::{AllowAnyAnonymousOrAuthenticatedClient, ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use sha2::{Digest, Sha256};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::server::TlsStream;
+use x509_parser::prelude::{FromDer, GeneralName, X509Certificate};
+
+use crate::validation::Username;
+
+/// Connections whose handshake has not completed within this time are
+/// dropped so they cannot tie up the accept loop's tasks forever.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serves the most recently loaded certificate, so it can be replaced
+/// without restarting the listener.
+pub struct CertResolver {
+    cert_file: String,
+    key_file: String,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl CertResolver {
+    pub fn from_config(config: &crate::config::Config) -> Result<Self, crate::error::Error> {
+        let (cert_file, key_file) = match (&config.tls_cert_file, &config.tls_key_file) {
+            (Some(cert_file), Some(key_file)) => (cert_file.clone(), key_file.clone()),
+            _ => {
+                return Err(crate::error::Error::ConfigurationError(String::from(
+                    "TLS_CERT_FILE",
+                )))
+            }
+        };
+        let key = load_certified_key(&cert_file, &key_file)?;
+        Ok(CertResolver {
+            cert_file,
+            key_file,
+            current: RwLock::new(Arc::new(key)),
+        })
+    }
+
+    /// Polls the certificate and key for changes and swaps them in. A
+    /// broken pair is logged and the previous one stays in use.
+    pub async fn watch(self: Arc<Self>, interval: Duration) {
+        let mut last_modified = self.modified();
+        loop {
+            tokio::time::sleep(interval).await;
+            let modified = self.modified();
+            if modified == last_modified {
+                continue;
+            }
+            match load_certified_key(&self.cert_file, &self.key_file) {
+                Ok(key) => {
+                    *self.current.write().unwrap() = Arc::new(key);
+                    last_modified = modified;
+                    tracing::event!(tracing::Level::INFO, "tls::certificate reloaded");
+                }
+                Err(e) => {
+                    tracing::event!(tracing::Level::ERROR, "tls::certificate reload {:?}", e)
+                }
+            }
+        }
+    }
+
+    fn modified(&self) -> Option<(SystemTime, SystemTime)> {
+        let modified = |path: &str| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        Some((modified(&self.cert_file)?, modified(&self.key_file)?))
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+/// TLS 1.2 and 1.3 only, with rustls' safe default suites and groups. With
+/// a client CA bundle, clients may present a certificate issued by it;
+/// clients without one can still authenticate by other means.
+pub fn server_config(
+    resolver: Arc<CertResolver>,
+    client_ca_file: Option<&str>,
+) -> Result<Arc<ServerConfig>, crate::error::Error> {
+    let builder = ServerConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(&[&rustls::version::TLS13, &rustls::version::TLS12])
+        .map_err(|e| crate::error::Error::CryptoError(e.to_string()))?;
+    let builder = match client_ca_file {
+        Some(client_ca_file) => builder.with_client_cert_verifier(
+            AllowAnyAnonymousOrAuthenticatedClient::new(load_roots(client_ca_file)?),
+        ),
+        None => builder.with_no_client_auth(),
+    };
+    let mut config = builder.with_cert_resolver(resolver);
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
+}
+
+/// Completed TLS connections for `hyper::Server::builder`. Handshakes run
+/// on their own tasks so one slow client cannot block the others.
+pub struct TlsIncoming {
+    connections: mpsc::Receiver<TlsStream<TcpStream>>,
+}
+
+impl TlsIncoming {
+    pub fn new(listener: TcpListener, config: Arc<ServerConfig>) -> Self {
+        let (sender, connections) = mpsc::channel(64);
+        let acceptor = tokio_rustls::TlsAcceptor::from(config);
+        tokio::spawn(async move {
+            loop {
+                let (stream, remote_addr) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        tracing::event!(tracing::Level::WARN, "tls::accept {:?}", e);
+                        continue;
+                    }
+                };
+                if sender.is_closed() {
+                    break;
+                }
+                let acceptor = acceptor.clone();
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = sender.send(stream).await;
+                        }
+                        Ok(Err(e)) => tracing::event!(
+                            tracing::Level::DEBUG,
+                            "tls::handshake with {} failed {:?}",
+                            remote_addr,
+                            e
+                        ),
+                        Err(_) => tracing::event!(
+                            tracing::Level::DEBUG,
+                            "tls::handshake with {} timed out",
+                            remote_addr
+                        ),
+                    }
+                });
+            }
+        });
+        TlsIncoming { connections }
+    }
+}
+
+impl Accept for TlsIncoming {
+    type Conn = TlsStream<TcpStream>;
+    type Error = std::io::Error;
+
+    fn poll_accept(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        self.connections.poll_recv(cx).map(|stream| stream.map(Ok))
+    }
+}
+
+/// Leaf certificate a client presented during the handshake, already
+/// validated against the client CA bundle.
+#[derive(Clone)]
+pub struct ClientCertificate {
+    der: Arc<Vec<u8>>,
+}
+
+impl ClientCertificate {
+    pub fn from_stream(stream: &TlsStream<TcpStream>) -> Option<Self> {
+        stream
+            .get_ref()
+            .1
+            .peer_certificates()
+            .and_then(|certs| certs.first())
+            .map(|cert| ClientCertificate {
+                der: Arc::new(cert.0.clone()),
+            })
+    }
+
+    /// RFC 8705 `x5t#S256` confirmation: base64url SHA-256 of the DER.
+    pub fn thumbprint(&self) -> String {
+        general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(self.der.as_slice()))
+    }
+
+    /// Name the certificate was issued to: the subject common name, or
+    /// else the first DNS or email subject alternative name. Names that are
+    /// not valid usernames cannot belong to any user and yield `None`.
+    pub fn principal(&self) -> Option<Username> {
+        let (_, cert) = X509Certificate::from_der(&self.der).ok()?;
+        let common_name = cert
+            .subject()
+            .iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+            .map(str::to_string);
+        let name = match common_name {
+            Some(cn) => cn,
+            None => {
+                let san = cert.subject_alternative_name().ok()??;
+                san.value.general_names.iter().find_map(|name| match name {
+                    GeneralName::DNSName(name) | GeneralName::RFC822Name(name) => {
+                        Some(name.to_string())
+                    }
+                    _ => None,
+                })?
+            }
+        };
+        Username::try_from(name).ok()
+    }
+}
+
+fn load_roots(client_ca_file: &str) -> Result<RootCertStore, crate::error::Error> {
+    let error = || crate::error::Error::ConfigurationError(String::from("TLS_CLIENT_CA_FILE"));
+    let mut reader = BufReader::new(fs::File::open(client_ca_file).map_err(|_| error())?);
+    let mut roots = RootCertStore::empty();
+    for cert in rustls_pemfile::certs(&mut reader).map_err(|_| error())? {
+        roots.add(&Certificate(cert)).map_err(|_| error())?;
+    }
+    if roots.is_empty() {
+        return Err(error());
+    }
+    Ok(roots)
+}
+
+fn load_certified_key(
+    cert_file: &str,
+    key_file: &str,
+) -> Result<CertifiedKey, crate::error::Error> {
+    let error = |name: &str| crate::error::Error::ConfigurationError(String::from(name));
+
+    let mut reader = BufReader::new(fs::File::open(cert_file).map_err(|_| error("TLS_CERT_FILE"))?);
+    let certs: Vec<Certificate> = rustls_pemfile::certs(&mut reader)
+        .map_err(|_| error("TLS_CERT_FILE"))?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    if certs.is_empty() {
+        return Err(error("TLS_CERT_FILE"));
+    }
+
+    let mut reader = BufReader::new(fs::File::open(key_file).map_err(|_| error("TLS_KEY_FILE"))?);
+    let key = loop {
+        match rustls_pemfile::read_one(&mut reader).map_err(|_| error("TLS_KEY_FILE"))? {
+            Some(rustls_pemfile::Item::PKCS8Key(key))
+            | Some(rustls_pemfile::Item::RSAKey(key))
+            | Some(rustls_pemfile::Item::ECKey(key)) => break PrivateKey(key),
+            Some(_) => continue,
+            None => return Err(error("TLS_KEY_FILE")),
+        }
+    };
+    let signing_key = rustls::sign::any_supported_type(&key).map_err(|_| error("TLS_KEY_FILE"))?;
+    Ok(CertifiedKey::new(certs, signing_key))
+}
+
+/// The only handler of the plain HTTP listener when TLS is enabled.
+pub async fn redirect_to_https(
+    State(https_port): State<u16>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> Response {
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .and_then(|host| host.parse::<Authority>().ok());
+    let Some(host) = host else {
+        return crate::error::Error::IllegalArgumentException(String::from("Missing Host header"))
+            .into_response();
+    };
+    let port = match https_port {
+        443 => String::new(),
+        port => format!(":{}", port),
+    };
+    let path = uri
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or("/");
+    Redirect::permanent(&format!("https://{}{}{}", host.host(), port, path)).into_response()
+}
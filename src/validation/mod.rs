@@ -0,0 +1,310 @@
+use std::ops::Deref;
+
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+use unicode_security::{skeleton as confusable_skeleton, MixedScript};
+
+const SPACE_NAME_MAX_CHARS: usize = 255;
+const MESSAGE_TEXT_MAX_CHARS: usize = 1024;
+const PERMISSIONS: &str = "rwd";
+
+/// Reason a value was rejected, reported to clients as invalid input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidationError(&'static str);
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl From<ValidationError> for crate::error::Error {
+    fn from(value: ValidationError) -> Self {
+        crate::error::Error::IllegalArgumentException(value.0.to_string())
+    }
+}
+
+/// An ASCII letter followed by 1 to 29 ASCII letters or digits, after NFKC
+/// normalization so compatibility forms such as full-width letters map to
+/// the plain name instead of creating a look-alike account. Names that are
+/// still confusable, such as `rnallory` and `mallory`, share a `skeleton`
+/// which the store keeps unique.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(try_from = "String")]
+pub struct Username(String);
+
+impl TryFrom<String> for Username {
+    type Error = ValidationError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match identifier(&value) {
+            Some(username) => Ok(Username(username)),
+            None => Err(ValidationError("Invalid username")),
+        }
+    }
+}
+
+/// Group names follow the same rules as usernames, including the unique
+/// skeleton.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(try_from = "String")]
+pub struct GroupName(String);
+
+impl TryFrom<String> for GroupName {
+    type Error = ValidationError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match identifier(&value) {
+            Some(name) => Ok(GroupName(name)),
+            None => Err(ValidationError("Invalid group name")),
+        }
+    }
+}
+
+/// Free-form, NFKC normalized name of up to 255 characters. Names mixing
+/// scripts, such as Latin with a Cyrillic look-alike letter, are rejected,
+/// and whole-script look-alikes share a `skeleton` which the store keeps
+/// unique, so a space cannot impersonate another one.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(try_from = "String")]
+pub struct SpaceName(String);
+
+impl TryFrom<String> for SpaceName {
+    type Error = ValidationError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let name: String = value.nfkc().collect();
+        if name.trim().is_empty() || name.chars().any(char::is_control) {
+            return Err(ValidationError("Invalid space name"));
+        }
+        if name.chars().count() > SPACE_NAME_MAX_CHARS {
+            return Err(ValidationError("Space name too long"));
+        }
+        if !name.as_str().is_single_script() {
+            return Err(ValidationError("Space name mixes scripts"));
+        }
+        Ok(SpaceName(name))
+    }
+}
+
+/// Message body of up to 1024 characters. Line breaks and tabs are the only
+/// control characters allowed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(try_from = "String")]
+pub struct MessageText(String);
+
+impl TryFrom<String> for MessageText {
+    type Error = ValidationError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if value.chars().count() > MESSAGE_TEXT_MAX_CHARS {
+            return Err(ValidationError("Message text too long"));
+        }
+        if value
+            .chars()
+            .any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t'))
+        {
+            return Err(ValidationError("Message text contains control characters"));
+        }
+        Ok(MessageText(value))
+    }
+}
+
+/// Space permissions as a combination of `r` (read), `w` (write) and `d`
+/// (delete), in that order. A grant must contain at least one of them.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct Permissions(String);
+
+impl TryFrom<String> for Permissions {
+    type Error = ValidationError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let mut allowed = PERMISSIONS.chars();
+        let ordered = value.chars().all(|perm| allowed.any(|c| c == perm));
+        if value.is_empty() || !ordered {
+            return Err(ValidationError("Invalid permissions"));
+        }
+        Ok(Permissions(value))
+    }
+}
+
+impl Permissions {
+    /// Combines grants, such as a user's own permissions on a space and those
+    /// of their groups. No grants at all allow nothing.
+    pub fn union<'a>(grants: impl IntoIterator<Item = &'a str>) -> Self {
+        let grants: Vec<&str> = grants.into_iter().collect();
+        Permissions(
+            PERMISSIONS
+                .chars()
+                .filter(|perm| grants.iter().any(|grant| grant.contains(*perm)))
+                .collect(),
+        )
+    }
+
+    pub fn allows(&self, required: &str) -> bool {
+        required.chars().all(|perm| self.0.contains(perm))
+    }
+}
+
+/// UTS #39 skeleton of a name. Two names are confusable exactly when their
+/// skeletons are equal.
+pub fn skeleton(name: &str) -> String {
+    confusable_skeleton(name).collect()
+}
+
+fn identifier(value: &str) -> Option<String> {
+    let normalized: String = value.nfkc().collect();
+    let mut chars = normalized.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && (2..=30).contains(&normalized.len())
+        && chars.all(|c| c.is_ascii_alphanumeric());
+    valid.then_some(normalized)
+}
+
+macro_rules! string_newtype {
+    ($($name:ident),*) => {$(
+        impl Deref for $name {
+            type Target = str;
+
+            fn deref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+    )*};
+}
+
+string_newtype!(Username, GroupName, SpaceName, MessageText, Permissions);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confusable_names_share_a_skeleton() {
+        for (name, look_alike) in [
+            ("mallory", "rnallory"),
+            ("paypal", "paypa1"),
+            ("bob0", "bobO"),
+            ("Space", "Ѕрасе"),
+        ] {
+            assert_eq!(skeleton(name), skeleton(look_alike), "{}", look_alike);
+        }
+    }
+
+    #[test]
+    fn usernames_are_nfkc_folded_ascii_identifiers() {
+        let username = Username::try_from(String::from("ａｌｉｃｅ")).unwrap();
+        assert_eq!(&*username, "alice");
+
+        assert!(Username::try_from("a".repeat(2)).is_ok());
+        assert!(Username::try_from("a".repeat(30)).is_ok());
+        for invalid in [
+            String::from("a"),
+            "a".repeat(31),
+            String::from("1alice"),
+            String::from("alíce"),
+            String::from("al ice"),
+            String::from("alice\u{7}"),
+            String::from("аlice"),
+        ] {
+            assert!(
+                Username::try_from(invalid.clone()).is_err(),
+                "{:?}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn space_names_are_single_script_without_control_characters() {
+        let name = SpaceName::try_from(String::from("Ｔｅａｍ Ｓｐａｃｅ")).unwrap();
+        assert_eq!(&*name, "Team Space");
+        assert!(SpaceName::try_from(String::from("Пространство")).is_ok());
+        assert!(SpaceName::try_from("x".repeat(SPACE_NAME_MAX_CHARS)).is_ok());
+
+        for (invalid, reason) in [
+            ("x".repeat(SPACE_NAME_MAX_CHARS + 1), "Space name too long"),
+            (String::from("Ѕpace"), "Space name mixes scripts"),
+            (String::from("Space\u{7}"), "Invalid space name"),
+            (String::from("Space\nName"), "Invalid space name"),
+            (String::from("  "), "Invalid space name"),
+        ] {
+            assert_eq!(
+                SpaceName::try_from(invalid.clone()),
+                Err(ValidationError(reason)),
+                "{:?}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn message_text_allows_line_breaks_and_tabs_only() {
+        assert!(MessageText::try_from(String::from("Hello,\r\n\tworld")).is_ok());
+        assert!(MessageText::try_from("é".repeat(MESSAGE_TEXT_MAX_CHARS)).is_ok());
+        assert_eq!(
+            MessageText::try_from("x".repeat(MESSAGE_TEXT_MAX_CHARS + 1)),
+            Err(ValidationError("Message text too long"))
+        );
+        for invalid in ["nul\u{0}", "bell\u{7}", "escape\u{1b}[2J", "next\u{85}line"] {
+            assert_eq!(
+                MessageText::try_from(invalid.to_string()),
+                Err(ValidationError("Message text contains control characters")),
+                "{:?}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn permissions_must_be_an_ordered_subset_of_rwd() {
+        for valid in ["r", "w", "d", "rw", "rd", "wd", "rwd"] {
+            assert!(
+                Permissions::try_from(valid.to_string()).is_ok(),
+                "{}",
+                valid
+            );
+        }
+        for invalid in ["", "x", "wr", "rr", "rwdr", "RWD", " r"] {
+            assert!(
+                Permissions::try_from(invalid.to_string()).is_err(),
+                "{:?}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn permissions_union_user_and_group_grants() {
+        let perms = Permissions::union(["r", "wd", "rw"]);
+        assert_eq!(&*perms, "rwd");
+        assert!(perms.allows("rwd"));
+
+        let perms = Permissions::union(["r", "r"]);
+        assert!(perms.allows("r"));
+        assert!(!perms.allows("w"));
+
+        let none = Permissions::union([]);
+        assert!(none.allows(""));
+        assert!(!none.allows("r"));
+    }
+
+    #[test]
+    fn distinct_names_have_distinct_skeletons() {
+        assert_ne!(skeleton("alice"), skeleton("alicia"));
+        assert_ne!(skeleton("bob"), skeleton("bobby"));
+    }
+}
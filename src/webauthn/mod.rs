@@ -0,0 +1,471 @@
+use base64::{engine::general_purpose, Engine as _};
+use ciborium::value::Value;
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use rand::Rng;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+/// COSE algorithm identifier for ECDSA with P-256 and SHA-256, the only
+/// algorithm Natter accepts for WebAuthn credentials.
+pub const COSE_ALG_ES256: i64 = -7;
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+const COSE_KEY_KTY: i64 = 1;
+const COSE_KEY_ALG: i64 = 3;
+const COSE_KEY_CRV: i64 = -1;
+const COSE_KEY_X: i64 = -2;
+const COSE_KEY_Y: i64 = -3;
+const COSE_KTY_EC2: i64 = 2;
+const COSE_CRV_P256: i64 = 1;
+
+#[derive(Deserialize, Debug)]
+pub struct ClientData {
+    #[serde(rename = "type")]
+    pub ceremony_type: String,
+    pub challenge: String,
+    pub origin: String,
+}
+
+/// Public key credential extracted from a verified registration ceremony.
+#[derive(Debug)]
+pub struct RegisteredCredential {
+    pub credential_id: String,
+    pub public_key: Vec<u8>,
+    pub sign_count: u32,
+}
+
+struct AuthenticatorData<'a> {
+    rp_id_hash: &'a [u8],
+    flags: u8,
+    sign_count: u32,
+    attested_credential_data: &'a [u8],
+}
+
+/// Validates WebAuthn ceremonies against the relying party configured for
+/// this Natter deployment.
+pub struct RelyingParty {
+    pub id: String,
+    pub name: String,
+    pub origin: String,
+}
+
+impl RelyingParty {
+    pub fn from_config(config: &crate::config::Config) -> Self {
+        RelyingParty {
+            id: config.webauthn_rp_id.to_string(),
+            name: String::from("Natter"),
+            origin: config.webauthn_origin.to_string(),
+        }
+    }
+
+    /// Checks the ceremony type and origin of the client data and returns it
+    /// so the caller can redeem the challenge.
+    pub fn verify_client_data(
+        &self,
+        client_data_json: &[u8],
+        ceremony_type: &str,
+    ) -> Result<ClientData, crate::error::Error> {
+        let client_data: ClientData =
+            serde_json::from_slice(client_data_json).map_err(|_| invalid_response())?;
+        if client_data.ceremony_type != ceremony_type {
+            return Err(verification_failed("Unexpected ceremony type"));
+        }
+        if client_data.origin != self.origin {
+            return Err(verification_failed("Unexpected origin"));
+        }
+        Ok(client_data)
+    }
+
+    /// Verifies an attestation object with the `none` attestation format and
+    /// extracts the new credential.
+    pub fn verify_registration(
+        &self,
+        attestation_object: &[u8],
+    ) -> Result<RegisteredCredential, crate::error::Error> {
+        let attestation: Value =
+            ciborium::de::from_reader(attestation_object).map_err(|_| invalid_response())?;
+        let fmt = map_get(&attestation, Value::Text(String::from("fmt")))
+            .and_then(Value::as_text)
+            .ok_or_else(invalid_response)?;
+        if fmt != "none" {
+            return Err(verification_failed("Unsupported attestation format"));
+        }
+        let auth_data = map_get(&attestation, Value::Text(String::from("authData")))
+            .and_then(Value::as_bytes)
+            .ok_or_else(invalid_response)?;
+
+        let auth_data = self.verify_authenticator_data(auth_data)?;
+        if auth_data.flags & FLAG_ATTESTED_CREDENTIAL_DATA == 0 {
+            return Err(verification_failed("Missing attested credential data"));
+        }
+
+        // aaguid (16 bytes) | credential id length (2 bytes) | credential id | COSE key
+        let attested = auth_data.attested_credential_data;
+        if attested.len() < 18 {
+            return Err(invalid_response());
+        }
+        let id_len = u16::from_be_bytes([attested[16], attested[17]]) as usize;
+        if attested.len() < 18 + id_len {
+            return Err(invalid_response());
+        }
+        let credential_id = &attested[18..18 + id_len];
+        let cose_key: Value =
+            ciborium::de::from_reader(&attested[18 + id_len..]).map_err(|_| invalid_response())?;
+
+        Ok(RegisteredCredential {
+            credential_id: general_purpose::URL_SAFE_NO_PAD.encode(credential_id),
+            public_key: parse_cose_key(&cose_key)?,
+            sign_count: auth_data.sign_count,
+        })
+    }
+
+    /// Verifies an assertion signature made with a registered credential and
+    /// returns the authenticator's new signature counter. A counter that did
+    /// not increase hints at a cloned authenticator; authenticators without a
+    /// counter always report 0.
+    pub fn verify_assertion(
+        &self,
+        public_key: &[u8],
+        stored_sign_count: u32,
+        authenticator_data: &[u8],
+        client_data_json: &[u8],
+        signature: &[u8],
+    ) -> Result<u32, crate::error::Error> {
+        let auth_data = self.verify_authenticator_data(authenticator_data)?;
+
+        let verifying_key =
+            VerifyingKey::from_sec1_bytes(public_key).map_err(|_| invalid_response())?;
+        let signature = Signature::from_der(signature).map_err(|_| invalid_response())?;
+        let mut signed_data = authenticator_data.to_vec();
+        signed_data.extend(Sha256::digest(client_data_json));
+
+        verifying_key
+            .verify(&signed_data, &signature)
+            .map_err(|_| verification_failed("Invalid signature"))?;
+
+        if auth_data.sign_count <= stored_sign_count
+            && (auth_data.sign_count != 0 || stored_sign_count != 0)
+        {
+            return Err(verification_failed("Signature counter did not increase"));
+        }
+        Ok(auth_data.sign_count)
+    }
+
+    fn verify_authenticator_data<'a>(
+        &self,
+        auth_data: &'a [u8],
+    ) -> Result<AuthenticatorData<'a>, crate::error::Error> {
+        if auth_data.len() < 37 {
+            return Err(invalid_response());
+        }
+        let auth_data = AuthenticatorData {
+            rp_id_hash: &auth_data[..32],
+            flags: auth_data[32],
+            sign_count: u32::from_be_bytes([
+                auth_data[33],
+                auth_data[34],
+                auth_data[35],
+                auth_data[36],
+            ]),
+            attested_credential_data: &auth_data[37..],
+        };
+
+        if auth_data.rp_id_hash != Sha256::digest(self.id.as_bytes()).as_slice() {
+            return Err(verification_failed("Unexpected relying party"));
+        }
+        // Passkeys replace the password entirely, so the authenticator must
+        // have verified the user and not only checked presence.
+        if auth_data.flags & FLAG_USER_PRESENT == 0 || auth_data.flags & FLAG_USER_VERIFIED == 0 {
+            return Err(verification_failed("User was not verified"));
+        }
+        Ok(auth_data)
+    }
+}
+
+pub fn generate_challenge() -> String {
+    general_purpose::URL_SAFE_NO_PAD.encode(rand::thread_rng().gen::<[u8; 32]>())
+}
+
+pub fn decode(value: &str) -> Result<Vec<u8>, crate::error::Error> {
+    general_purpose::URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| invalid_response())
+}
+
+/// Converts an EC2 P-256 COSE key into an uncompressed SEC1 point.
+fn parse_cose_key(cose_key: &Value) -> Result<Vec<u8>, crate::error::Error> {
+    let int_entry =
+        |label: i64| map_get(cose_key, Value::Integer(label.into())).and_then(Value::as_integer);
+    let bytes_entry =
+        |label: i64| map_get(cose_key, Value::Integer(label.into())).and_then(Value::as_bytes);
+
+    if int_entry(COSE_KEY_KTY) != Some(COSE_KTY_EC2.into())
+        || int_entry(COSE_KEY_ALG) != Some(COSE_ALG_ES256.into())
+        || int_entry(COSE_KEY_CRV) != Some(COSE_CRV_P256.into())
+    {
+        return Err(verification_failed("Unsupported credential algorithm"));
+    }
+    match (bytes_entry(COSE_KEY_X), bytes_entry(COSE_KEY_Y)) {
+        (Some(x), Some(y)) if x.len() == 32 && y.len() == 32 => {
+            let mut point = vec![0x04];
+            point.extend(x);
+            point.extend(y);
+            VerifyingKey::from_sec1_bytes(&point).map_err(|_| invalid_response())?;
+            Ok(point)
+        }
+        _ => Err(invalid_response()),
+    }
+}
+
+fn map_get(map: &Value, key: Value) -> Option<&Value> {
+    map.as_map()?
+        .iter()
+        .find(|(entry_key, _)| *entry_key == key)
+        .map(|(_, value)| value)
+}
+
+fn invalid_response() -> crate::error::Error {
+    crate::error::Error::IllegalArgumentException(String::from("Invalid WebAuthn response"))
+}
+
+fn verification_failed(reason: &str) -> crate::error::Error {
+    crate::error::Error::AuthenticationError(String::from(reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p256::ecdsa::{signature::Signer, SigningKey};
+
+    const RP_ID: &str = "natter.example";
+    const ORIGIN: &str = "https://natter.example";
+
+    fn relying_party() -> RelyingParty {
+        RelyingParty {
+            id: String::from(RP_ID),
+            name: String::from("Natter"),
+            origin: String::from(ORIGIN),
+        }
+    }
+
+    /// Minimal software authenticator holding a single P-256 credential.
+    struct SoftwareAuthenticator {
+        key: SigningKey,
+        credential_id: Vec<u8>,
+    }
+
+    impl SoftwareAuthenticator {
+        fn new() -> Self {
+            SoftwareAuthenticator {
+                key: SigningKey::random(&mut rand::rngs::OsRng),
+                credential_id: rand::thread_rng().gen::<[u8; 16]>().to_vec(),
+            }
+        }
+
+        fn public_key(&self) -> Vec<u8> {
+            VerifyingKey::from(&self.key)
+                .to_encoded_point(false)
+                .as_bytes()
+                .to_vec()
+        }
+
+        fn auth_data(rp_id: &str, flags: u8, sign_count: u32) -> Vec<u8> {
+            let mut auth_data = Sha256::digest(rp_id.as_bytes()).to_vec();
+            auth_data.push(flags);
+            auth_data.extend(sign_count.to_be_bytes());
+            auth_data
+        }
+
+        fn attestation_object(&self, flags: u8) -> Vec<u8> {
+            let point = VerifyingKey::from(&self.key).to_encoded_point(false);
+            let cose_key = Value::Map(vec![
+                (COSE_KEY_KTY.into(), COSE_KTY_EC2.into()),
+                (COSE_KEY_ALG.into(), COSE_ALG_ES256.into()),
+                (COSE_KEY_CRV.into(), COSE_CRV_P256.into()),
+                (COSE_KEY_X.into(), Value::Bytes(point.x().unwrap().to_vec())),
+                (COSE_KEY_Y.into(), Value::Bytes(point.y().unwrap().to_vec())),
+            ]);
+
+            let mut auth_data = Self::auth_data(RP_ID, flags, 0);
+            auth_data.extend([0u8; 16]);
+            auth_data.extend((self.credential_id.len() as u16).to_be_bytes());
+            auth_data.extend(&self.credential_id);
+            ciborium::ser::into_writer(&cose_key, &mut auth_data).unwrap();
+
+            let attestation = Value::Map(vec![
+                ("fmt".into(), "none".into()),
+                ("attStmt".into(), Value::Map(vec![])),
+                ("authData".into(), Value::Bytes(auth_data)),
+            ]);
+            let mut attestation_object = vec![];
+            ciborium::ser::into_writer(&attestation, &mut attestation_object).unwrap();
+            attestation_object
+        }
+
+        /// Returns authenticator data, client data JSON and DER signature.
+        fn assertion(&self, rp_id: &str, flags: u8, sign_count: u32) -> Assertion {
+            let authenticator_data = Self::auth_data(rp_id, flags, sign_count);
+            let client_data_json = client_data("webauthn.get", ORIGIN);
+            let mut signed_data = authenticator_data.clone();
+            signed_data.extend(Sha256::digest(&client_data_json));
+            let signature: Signature = self.key.sign(&signed_data);
+            Assertion {
+                authenticator_data,
+                client_data_json,
+                signature: signature.to_der().as_bytes().to_vec(),
+            }
+        }
+    }
+
+    struct Assertion {
+        authenticator_data: Vec<u8>,
+        client_data_json: Vec<u8>,
+        signature: Vec<u8>,
+    }
+
+    impl Assertion {
+        fn verify(
+            &self,
+            public_key: &[u8],
+            stored_sign_count: u32,
+        ) -> Result<u32, crate::error::Error> {
+            relying_party().verify_assertion(
+                public_key,
+                stored_sign_count,
+                &self.authenticator_data,
+                &self.client_data_json,
+                &self.signature,
+            )
+        }
+    }
+
+    fn client_data(ceremony_type: &str, origin: &str) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "type": ceremony_type,
+            "challenge": generate_challenge(),
+            "origin": origin,
+        }))
+        .unwrap()
+    }
+
+    const FLAGS_UP_UV: u8 = FLAG_USER_PRESENT | FLAG_USER_VERIFIED;
+
+    #[test]
+    fn accepts_software_authenticator_registration() {
+        let authenticator = SoftwareAuthenticator::new();
+        let credential = relying_party()
+            .verify_registration(
+                &authenticator.attestation_object(FLAGS_UP_UV | FLAG_ATTESTED_CREDENTIAL_DATA),
+            )
+            .unwrap();
+
+        assert_eq!(
+            credential.credential_id,
+            general_purpose::URL_SAFE_NO_PAD.encode(&authenticator.credential_id)
+        );
+        assert_eq!(credential.public_key, authenticator.public_key());
+        assert_eq!(credential.sign_count, 0);
+    }
+
+    #[test]
+    fn accepts_software_authenticator_assertion() {
+        let authenticator = SoftwareAuthenticator::new();
+        let assertion = authenticator.assertion(RP_ID, FLAGS_UP_UV, 5);
+
+        assert_eq!(assertion.verify(&authenticator.public_key(), 4).unwrap(), 5);
+    }
+
+    #[test]
+    fn rejects_wrong_origin() {
+        let result = relying_party().verify_client_data(
+            &client_data("webauthn.get", "https://evil.example"),
+            "webauthn.get",
+        );
+
+        assert!(matches!(
+            result,
+            Err(crate::error::Error::AuthenticationError(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_wrong_ceremony_type() {
+        let result = relying_party()
+            .verify_client_data(&client_data("webauthn.create", ORIGIN), "webauthn.get");
+
+        assert!(matches!(
+            result,
+            Err(crate::error::Error::AuthenticationError(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_wrong_rp_id_hash() {
+        let authenticator = SoftwareAuthenticator::new();
+        let assertion = authenticator.assertion("evil.example", FLAGS_UP_UV, 1);
+
+        assert!(matches!(
+            assertion.verify(&authenticator.public_key(), 0),
+            Err(crate::error::Error::AuthenticationError(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_missing_user_verification() {
+        let authenticator = SoftwareAuthenticator::new();
+        let assertion = authenticator.assertion(RP_ID, FLAG_USER_PRESENT, 1);
+        let registration = relying_party().verify_registration(
+            &authenticator.attestation_object(FLAG_USER_PRESENT | FLAG_ATTESTED_CREDENTIAL_DATA),
+        );
+
+        assert!(matches!(
+            assertion.verify(&authenticator.public_key(), 0),
+            Err(crate::error::Error::AuthenticationError(_))
+        ));
+        assert!(matches!(
+            registration,
+            Err(crate::error::Error::AuthenticationError(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_signature() {
+        let authenticator = SoftwareAuthenticator::new();
+        let mut assertion = authenticator.assertion(RP_ID, FLAGS_UP_UV, 1);
+        // Sign over different client data than the one presented.
+        assertion.client_data_json = client_data("webauthn.get", ORIGIN);
+
+        assert!(matches!(
+            assertion.verify(&authenticator.public_key(), 0),
+            Err(crate::error::Error::AuthenticationError(_))
+        ));
+
+        // A valid signature from another authenticator.
+        let other = SoftwareAuthenticator::new();
+        let assertion = other.assertion(RP_ID, FLAGS_UP_UV, 1);
+        assert!(matches!(
+            assertion.verify(&authenticator.public_key(), 0),
+            Err(crate::error::Error::AuthenticationError(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_counter_that_does_not_increase() {
+        let authenticator = SoftwareAuthenticator::new();
+        let public_key = authenticator.public_key();
+
+        for stored_sign_count in [7, 8] {
+            let assertion = authenticator.assertion(RP_ID, FLAGS_UP_UV, 7);
+            assert!(matches!(
+                assertion.verify(&public_key, stored_sign_count),
+                Err(crate::error::Error::AuthenticationError(_))
+            ));
+        }
+        // Authenticators without a counter always report 0.
+        let assertion = authenticator.assertion(RP_ID, FLAGS_UP_UV, 0);
+        assert_eq!(assertion.verify(&public_key, 0).unwrap(), 0);
+    }
+}